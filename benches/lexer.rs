@@ -0,0 +1,87 @@
+// Compares lexing with tokens borrowed from the source against detaching every token with
+// `into_owned`, which is what the lexer did before tokens could borrow. Run it with
+// `cargo bench --bench lexer`.
+
+use interpreter::lexer::Lexer;
+use interpreter::token::Token;
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::hint::black_box;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Instant;
+
+/// Counts the allocations made through it, to see how many the lexer makes.
+struct CountingAllocator;
+
+static ALLOCATIONS: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        System.realloc(ptr, layout, new_size)
+    }
+}
+
+#[global_allocator]
+static ALLOCATOR: CountingAllocator = CountingAllocator;
+
+const ITERATIONS: usize = 20;
+
+/// A script of a few thousand lines exercising every kind of token.
+fn script() -> String {
+    let snippet = r#"let fib = fn(n) {
+    if (n < 2) { return n; } else { fib(n - 1) + fib(n - 2) }
+};
+let people = [{"name": "Ada", "age": 36}, {"name": "Alan", "age": 41}];
+let greet = fn(person) { "Hello, ${person["name"]}!\n" };
+puts(greet(people[0]), len(people) * 10 / 2 == 10, !true != false);
+"#;
+    snippet.repeat(1000)
+}
+
+/// Lexes `source` with `lex` and prints the allocations and time it took per run.
+fn measure(name: &str, source: &str, lex: impl Fn(&str) -> usize) {
+    let tokens = lex(source);
+    let allocations = ALLOCATIONS.load(Ordering::Relaxed);
+    let start = Instant::now();
+    for _ in 0..ITERATIONS {
+        black_box(lex(black_box(source)));
+    }
+    let elapsed = start.elapsed() / ITERATIONS as u32;
+    let allocations = (ALLOCATIONS.load(Ordering::Relaxed) - allocations) / ITERATIONS;
+    println!(
+        "{:<10} {:>8} tokens {:>8} allocations {:>10.2?}",
+        name, tokens, allocations, elapsed
+    );
+}
+
+fn main() {
+    let source = script();
+    println!("lexing {} bytes, {} runs each", source.len(), ITERATIONS);
+    measure("borrowed", &source, |source| {
+        let mut lexer = Lexer::new(source);
+        let mut count = 0;
+        while let Some(token) = lexer.next_token() {
+            black_box(token);
+            count += 1;
+        }
+        count
+    });
+    measure("owned", &source, |source| {
+        let mut lexer = Lexer::new(source);
+        let mut count = 0;
+        while let Some(token) = lexer.next_token() {
+            black_box(Token::into_owned(token));
+            count += 1;
+        }
+        count
+    });
+}
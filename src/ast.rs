@@ -1,102 +1,1078 @@
 use crate::lexer::Lexer;
-use crate::token::Token;
+use crate::token::{self, OwnedToken, Span, SpannedToken, StringPart, Token};
+use std::fmt;
 
-#[derive(Debug, PartialEq, Eq)]
-pub enum Expression {
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum PrefixOperator {
+    Bang,
+    Minus,
+}
+
+impl fmt::Display for PrefixOperator {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PrefixOperator::Bang => write!(f, "!"),
+            PrefixOperator::Minus => write!(f, "-"),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum InfixOperator {
+    Plus,
+    Minus,
+    Asterisk,
+    Slash,
+    LessThan,
+    GreaterThan,
+    Equal,
+    NotEqual,
+}
+
+impl InfixOperator {
+    fn from_token(token: &Token) -> Option<Self> {
+        match token {
+            Token::Plus => Some(InfixOperator::Plus),
+            Token::Minus => Some(InfixOperator::Minus),
+            Token::Asterisk => Some(InfixOperator::Asterisk),
+            Token::Slash => Some(InfixOperator::Slash),
+            Token::LessThan => Some(InfixOperator::LessThan),
+            Token::GreaterThan => Some(InfixOperator::GreaterThan),
+            Token::Equal => Some(InfixOperator::Equal),
+            Token::NotEqual => Some(InfixOperator::NotEqual),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for InfixOperator {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let operator = match self {
+            InfixOperator::Plus => "+",
+            InfixOperator::Minus => "-",
+            InfixOperator::Asterisk => "*",
+            InfixOperator::Slash => "/",
+            InfixOperator::LessThan => "<",
+            InfixOperator::GreaterThan => ">",
+            InfixOperator::Equal => "==",
+            InfixOperator::NotEqual => "!=",
+        };
+        write!(f, "{}", operator)
+    }
+}
+
+/// Binding power of the operators, from weakest to strongest.
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Clone, Copy)]
+enum Precedence {
+    Lowest,
+    Equals,
+    LessGreater,
+    Sum,
+    Product,
+    Prefix,
+    Call,
+    Index,
+}
+
+impl Precedence {
+    fn of(token: &Token) -> Self {
+        match token {
+            Token::Equal | Token::NotEqual => Precedence::Equals,
+            Token::LessThan | Token::GreaterThan => Precedence::LessGreater,
+            Token::Plus | Token::Minus => Precedence::Sum,
+            Token::Asterisk | Token::Slash => Precedence::Product,
+            Token::LParen => Precedence::Call,
+            Token::LBracket => Precedence::Index,
+            _ => Precedence::Lowest,
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct Expression {
+    pub kind: ExpressionKind,
+    pub span: Span,
+}
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum ExpressionKind {
     IntegerLiteral(i64),
+    BooleanLiteral(bool),
+    StringLiteral(String),
+    InterpolatedString(Vec<InterpolationPart>),
     IdentifierLiteral(String),
+    Prefix {
+        operator: PrefixOperator,
+        right: Box<Expression>,
+    },
+    Infix {
+        left: Box<Expression>,
+        operator: InfixOperator,
+        right: Box<Expression>,
+    },
+    If {
+        condition: Box<Expression>,
+        consequence: BlockStatement,
+        alternative: Option<BlockStatement>,
+    },
+    FunctionLiteral {
+        parameters: Vec<Identifier>,
+        body: BlockStatement,
+    },
+    Call {
+        function: Box<Expression>,
+        arguments: Vec<Expression>,
+    },
+    ArrayLiteral(Vec<Expression>),
+    /// Key-value pairs in source order.
+    HashLiteral(Vec<(Expression, Expression)>),
+    Index {
+        left: Box<Expression>,
+        index: Box<Expression>,
+    },
+}
+
+impl fmt::Display for Expression {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.kind {
+            ExpressionKind::IntegerLiteral(value) => write!(f, "{}", value),
+            ExpressionKind::BooleanLiteral(value) => write!(f, "{}", value),
+            ExpressionKind::StringLiteral(value) => write!(f, "\"{}\"", token::escape(value)),
+            ExpressionKind::InterpolatedString(parts) => {
+                write!(f, "\"")?;
+                for part in parts {
+                    match part {
+                        InterpolationPart::Literal(text) => write!(f, "{}", token::escape(text))?,
+                        InterpolationPart::Expression(expression) => {
+                            write!(f, "${{{}}}", expression)?
+                        }
+                    }
+                }
+                write!(f, "\"")
+            }
+            ExpressionKind::IdentifierLiteral(name) => write!(f, "{}", name),
+            ExpressionKind::Prefix { operator, right } => write!(f, "({}{})", operator, right),
+            ExpressionKind::Infix {
+                left,
+                operator,
+                right,
+            } => write!(f, "({} {} {})", left, operator, right),
+            ExpressionKind::If {
+                condition,
+                consequence,
+                alternative,
+            } => {
+                write!(f, "if {} {}", condition, consequence)?;
+                if let Some(alternative) = alternative {
+                    write!(f, " else {}", alternative)?;
+                }
+                Ok(())
+            }
+            ExpressionKind::FunctionLiteral { parameters, body } => {
+                let parameters: Vec<&str> = parameters.iter().map(|p| p.name.as_str()).collect();
+                write!(f, "fn({}) {}", parameters.join(", "), body)
+            }
+            ExpressionKind::Call {
+                function,
+                arguments,
+            } => {
+                let arguments: Vec<String> = arguments.iter().map(|a| a.to_string()).collect();
+                write!(f, "{}({})", function, arguments.join(", "))
+            }
+            ExpressionKind::ArrayLiteral(elements) => {
+                let elements: Vec<String> = elements.iter().map(|e| e.to_string()).collect();
+                write!(f, "[{}]", elements.join(", "))
+            }
+            ExpressionKind::HashLiteral(pairs) => {
+                let pairs: Vec<String> = pairs
+                    .iter()
+                    .map(|(key, value)| format!("{}: {}", key, value))
+                    .collect();
+                write!(f, "{{{}}}", pairs.join(", "))
+            }
+            ExpressionKind::Index { left, index } => write!(f, "({}[{}])", left, index),
+        }
+    }
+}
+
+/// A piece of an interpolated string such as `"hello ${name}"`.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum InterpolationPart {
+    Literal(String),
+    Expression(Expression),
+}
+
+/// A name introduced by a `let` statement or a function parameter list.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct Identifier {
+    pub name: String,
+    pub span: Span,
 }
-#[derive(Debug, PartialEq, Eq)]
+
+impl fmt::Display for Identifier {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.name)
+    }
+}
+
+/// A `{ ... }` delimited list of statements, used as the body of functions and `if` branches.
+#[derive(Debug, PartialEq, Eq, Clone, Default)]
+pub struct BlockStatement {
+    pub statements: Vec<Statement>,
+    pub span: Span,
+}
+
+impl fmt::Display for BlockStatement {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{{")?;
+        for statement in &self.statements {
+            write!(f, " {}", statement)?;
+        }
+        write!(f, " }}")
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Clone)]
 pub struct LetStatement {
-    variable: String,
-    expression: Expression,
+    pub variable: Identifier,
+    pub expression: Expression,
 }
-#[derive(Debug, PartialEq, Eq)]
-pub enum Statement {
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct Statement {
+    pub kind: StatementKind,
+    pub span: Span,
+}
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum StatementKind {
     Let(LetStatement),
+    Return(Expression),
+    Expression(Expression),
+}
+
+impl fmt::Display for Statement {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.kind {
+            StatementKind::Let(LetStatement {
+                variable,
+                expression,
+            }) => write!(f, "let {} = {};", variable, expression),
+            StatementKind::Return(expression) => write!(f, "return {};", expression),
+            StatementKind::Expression(expression) => write!(f, "{}", expression),
+        }
+    }
+}
+
+/// The root node of every parsed source.
+#[derive(Debug, PartialEq, Eq, Default)]
+pub struct Program {
+    pub statements: Vec<Statement>,
+    pub span: Span,
+}
+
+impl fmt::Display for Program {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for statement in &self.statements {
+            writeln!(f, "{}", statement)?;
+        }
+        Ok(())
+    }
+}
+
+/// What the parser was looking for when it found an unexpected token.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum Expected {
+    Token(OwnedToken),
+    Identifier,
+    Expression,
+    Operator,
+    /// An integer literal that fits into an `i64`.
+    Integer,
+    /// Anything that doesn't nest the expression deeper than `MAX_NESTING`.
+    LessNesting,
+}
+
+impl fmt::Display for Expected {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Expected::Token(token) => write!(f, "`{}`", token),
+            Expected::Identifier => write!(f, "an identifier"),
+            Expected::Expression => write!(f, "an expression"),
+            Expected::Operator => write!(f, "an operator"),
+            Expected::Integer => write!(f, "a 64-bit integer"),
+            Expected::LessNesting => write!(f, "a less nested expression"),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct ParseError {
+    pub expected: Expected,
+    pub found: OwnedToken,
+    /// The location of the `found` token.
+    pub span: Span,
 }
 
+impl ParseError {
+    fn new(expected: Expected, found: SpannedToken) -> Self {
+        Self {
+            expected,
+            found: found.token.into_owned(),
+            span: found.span,
+        }
+    }
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.expected == Expected::LessNesting {
+            return write!(
+                f,
+                "expression nested too deeply, the limit is {}",
+                MAX_NESTING
+            );
+        }
+        match self.found {
+            Token::Eof => write!(f, "expected {}, found end of input", self.expected),
+            Token::UnterminatedString => write!(f, "unterminated string"),
+            Token::InvalidEscape(ref text) => write!(f, "invalid escape sequence `{}`", text),
+            _ => write!(f, "expected {}, found `{}`", self.expected, self.found),
+        }
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+/// How deeply expressions can nest, so that parsing them and everything that walks them after
+/// stays within the stack of a thread spawned with the default size.
+pub const MAX_NESTING: usize = 128;
+
 #[derive(Debug)]
-pub struct Parser {
-    tokens: Vec<Token>,
+pub struct Parser<'src> {
+    lexer: Lexer<'src>,
+    /// The next token, which stays `Token::Eof` once the input is exhausted.
+    peeked: SpannedToken<'src>,
+    errors: Vec<ParseError>,
+    /// The span of the most recently consumed token, which ends the node being parsed.
+    previous: Span,
+    /// The number of `{` consumed and not yet closed, to recover from errors at the right level.
+    depth: usize,
+    /// The number of expressions being parsed, each nested in the previous one.
+    nesting: usize,
 }
 
-impl Parser {
-    pub fn new(input: &str) -> Self {
-        let mut lexer = Lexer::new(input);
-        let mut tokens: Vec<Token> = Vec::new();
-        while let Some(token) = lexer.next_token() {
-            tokens.push(token);
+impl<'src> Parser<'src> {
+    pub fn new(input: &'src str) -> Self {
+        Self::from_lexer(Lexer::new(input))
+    }
+
+    /// Creates a `Parser` for `input` found at `origin` within a larger source.
+    fn new_at(input: &'src str, origin: Span) -> Self {
+        Self::from_lexer(Lexer::new_at(input, origin))
+    }
+
+    fn from_lexer(mut lexer: Lexer<'src>) -> Self {
+        let peeked = lexer.next_spanned_token();
+        Self {
+            lexer,
+            previous: peeked.span,
+            peeked,
+            errors: Vec::new(),
+            depth: 0,
+            nesting: 0,
         }
-        tokens.reverse();
-        Self { tokens }
     }
 
-    fn next(&mut self) -> Token {
-        if let Some(token) = self.tokens.pop() {
-            token
-        } else {
-            Token::Eof
+    fn next(&mut self) -> SpannedToken<'src> {
+        let token = std::mem::replace(&mut self.peeked, self.lexer.next_spanned_token());
+        self.previous = token.span;
+        match token.token {
+            Token::LBrace => self.depth += 1,
+            Token::RBrace => self.depth = self.depth.saturating_sub(1),
+            _ => {}
         }
+        token
     }
 
-    fn peek(&mut self) -> Token {
-        if let Some(_token) = self.tokens.last() {
-            self.tokens.clone().last().unwrap().clone()
+    fn peek(&self) -> &Token<'src> {
+        &self.peeked.token
+    }
+
+    fn peek_spanned(&self) -> &SpannedToken<'src> {
+        &self.peeked
+    }
+
+    /// Consumes the next token, failing if it isn't `expected`.
+    fn expect(&mut self, expected: OwnedToken) -> Result<(), ParseError> {
+        let token = self.next();
+        if token.token == expected {
+            Ok(())
         } else {
-            Token::Eof
+            Err(ParseError::new(Expected::Token(expected), token))
         }
     }
 
-    pub fn parse(&mut self) -> Vec<Statement> {
+    /// Returns the span from `start` up to the end of the most recently consumed token.
+    fn span_from(&self, start: Span) -> Span {
+        start.to(self.previous)
+    }
+
+    /// Parses statements until `Token::Eof` is reached.
+    ///
+    /// A statement that fails to parse is recorded and skipped, so that every error in the input
+    /// is reported at once.
+    pub fn parse(&mut self) -> Result<Program, Vec<ParseError>> {
+        let start = self.peek_spanned().span;
         let mut statements: Vec<Statement> = Vec::new();
-        let token = self.peek();
-        match token {
-            Token::Let => statements.push(self.parse_let_statement()),
-            _ => panic!("bad token {:?}", token),
+        while *self.peek() != Token::Eof {
+            let depth = self.depth;
+            match self.parse_statement() {
+                Ok(statement) => statements.push(statement),
+                Err(error) => {
+                    self.errors.push(error);
+                    self.synchronize(depth);
+                }
+            }
+        }
+        if self.errors.is_empty() {
+            Ok(Program {
+                statements,
+                span: start.to(self.peeked.span),
+            })
+        } else {
+            Err(std::mem::take(&mut self.errors))
         }
-        statements
     }
-    fn parse_let_statement(&mut self) -> Statement {
+
+    /// Skips tokens up to the next boundary of a statement that started inside `depth` braces:
+    /// past a `;`, or up to a `let`, a `return` or the `}` closing the enclosing block. Braces
+    /// opened after the statement started are skipped up to where they close.
+    fn synchronize(&mut self, depth: usize) {
+        loop {
+            let nested = self.depth > depth;
+            match self.peek() {
+                Token::Eof => return,
+                Token::Let | Token::Return if !nested => return,
+                Token::RBrace if !nested && depth > 0 => return,
+                Token::Semicolon if !nested => {
+                    self.next();
+                    return;
+                }
+                _ => {
+                    self.next();
+                }
+            }
+        }
+    }
+
+    fn parse_statement(&mut self) -> Result<Statement, ParseError> {
+        let start = self.peek_spanned().span;
+        let kind = match self.peek() {
+            Token::Let => self.parse_let_statement()?,
+            Token::Return => self.parse_return_statement()?,
+            _ => self.parse_expression_statement()?,
+        };
+        Ok(Statement {
+            kind,
+            span: self.span_from(start),
+        })
+    }
+
+    fn parse_let_statement(&mut self) -> Result<StatementKind, ParseError> {
+        self.expect(Token::Let)?;
+        let variable = self.parse_identifier()?;
+        self.expect(Token::Assign)?;
+        let expression = self.parse_expression(Precedence::Lowest)?;
+        self.skip_semicolon();
+        Ok(StatementKind::Let(LetStatement {
+            variable,
+            expression,
+        }))
+    }
+
+    fn parse_return_statement(&mut self) -> Result<StatementKind, ParseError> {
+        self.expect(Token::Return)?;
+        let expression = self.parse_expression(Precedence::Lowest)?;
+        self.skip_semicolon();
+        Ok(StatementKind::Return(expression))
+    }
+
+    fn parse_expression_statement(&mut self) -> Result<StatementKind, ParseError> {
+        let expression = self.parse_expression(Precedence::Lowest)?;
+        self.skip_semicolon();
+        Ok(StatementKind::Expression(expression))
+    }
+
+    /// Statement-terminating semicolons are optional, so `5 + 5` parses like `5 + 5;`.
+    fn skip_semicolon(&mut self) {
+        if *self.peek() == Token::Semicolon {
+            self.next();
+        }
+    }
+
+    fn parse_identifier(&mut self) -> Result<Identifier, ParseError> {
+        let token = self.next();
+        match token.token {
+            Token::Ident(name) => Ok(Identifier {
+                name: name.into_owned(),
+                span: token.span,
+            }),
+            _ => Err(ParseError::new(Expected::Identifier, token)),
+        }
+    }
+
+    fn parse_expression(&mut self, precedence: Precedence) -> Result<Expression, ParseError> {
+        if self.nesting == MAX_NESTING {
+            return Err(ParseError::new(Expected::LessNesting, self.next()));
+        }
+        self.nesting += 1;
+        let expression = self.parse_nested_expression(precedence);
+        self.nesting -= 1;
+        expression
+    }
+
+    fn parse_nested_expression(
+        &mut self,
+        precedence: Precedence,
+    ) -> Result<Expression, ParseError> {
+        let mut left = self.parse_prefix()?;
+        while *self.peek() != Token::Semicolon && precedence < Precedence::of(self.peek()) {
+            left = self.parse_infix(left)?;
+        }
+        Ok(left)
+    }
+
+    fn parse_prefix(&mut self) -> Result<Expression, ParseError> {
         let token = self.next();
-        assert_eq!(token, Token::Let);
-        let variable = match self.next() {
-            Token::Ident(it) => it,
-            _ => panic!("bad token {:?}", token),
+        let start = token.span;
+        let kind = match token.token {
+            Token::Int(ref number) => match number.parse() {
+                Ok(value) => ExpressionKind::IntegerLiteral(value),
+                Err(_) => return Err(ParseError::new(Expected::Integer, token)),
+            },
+            Token::Str(value) => ExpressionKind::StringLiteral(value.into_owned()),
+            Token::Interpolated(parts) => self.parse_interpolated_string(parts)?,
+            Token::True => ExpressionKind::BooleanLiteral(true),
+            Token::False => ExpressionKind::BooleanLiteral(false),
+            Token::Ident(ident) => ExpressionKind::IdentifierLiteral(ident.into_owned()),
+            Token::Bang => self.parse_prefix_operator(PrefixOperator::Bang)?,
+            Token::Minus => self.parse_prefix_operator(PrefixOperator::Minus)?,
+            Token::LParen => {
+                let expression = self.parse_expression(Precedence::Lowest)?;
+                self.expect(Token::RParen)?;
+                expression.kind
+            }
+            Token::LBracket => {
+                ExpressionKind::ArrayLiteral(self.parse_expression_list(Token::RBracket)?)
+            }
+            // Blocks only follow `if`, `else` and `fn(...)`, so a brace in expression position
+            // always opens a hash.
+            Token::LBrace => self.parse_hash_literal()?,
+            Token::If => self.parse_if_expression()?,
+            Token::Function => self.parse_function_literal()?,
+            _ => return Err(ParseError::new(Expected::Expression, token)),
         };
+        Ok(Expression {
+            kind,
+            span: self.span_from(start),
+        })
+    }
+
+    /// Parses `key: value` pairs, assuming the `{` has already been consumed.
+    fn parse_hash_literal(&mut self) -> Result<ExpressionKind, ParseError> {
+        let mut pairs = Vec::new();
+        while *self.peek() != Token::RBrace {
+            let key = self.parse_expression(Precedence::Lowest)?;
+            self.expect(Token::Colon)?;
+            let value = self.parse_expression(Precedence::Lowest)?;
+            pairs.push((key, value));
+            if *self.peek() != Token::RBrace {
+                self.expect(Token::Comma)?;
+            }
+        }
+        self.next();
+        Ok(ExpressionKind::HashLiteral(pairs))
+    }
+
+    fn parse_interpolated_string(
+        &self,
+        parts: Vec<StringPart>,
+    ) -> Result<ExpressionKind, ParseError> {
+        let parts = parts
+            .into_iter()
+            .map(|part| match part {
+                StringPart::Literal(text) => Ok(InterpolationPart::Literal(text.into_owned())),
+                StringPart::Code { source, span } => {
+                    // The embedded code nests in the string's expression.
+                    let mut parser = Parser::new_at(&source, span);
+                    parser.nesting = self.nesting;
+                    parser
+                        .parse_embedded_expression()
+                        .map(InterpolationPart::Expression)
+                }
+            })
+            .collect::<Result<_, _>>()?;
+        Ok(ExpressionKind::InterpolatedString(parts))
+    }
+
+    /// Parses the code of a `${...}` interpolation, which must be a single expression.
+    ///
+    /// The code ends at the closing `}`, so that is what an error reports instead of the end of
+    /// the input.
+    fn parse_embedded_expression(&mut self) -> Result<Expression, ParseError> {
+        let at_closing_brace = |mut error: ParseError| {
+            if error.found == Token::Eof {
+                error.found = Token::RBrace;
+            }
+            error
+        };
+        let expression = self
+            .parse_expression(Precedence::Lowest)
+            .map_err(at_closing_brace)?;
+        // Errors in the blocks of the expression were recovered from, but still fail it.
+        if !self.errors.is_empty() {
+            return Err(at_closing_brace(self.errors.remove(0)));
+        }
         let token = self.next();
-        assert_eq!(token, Token::Assign);
-        let expression = self.parse_expression();
+        if token.token != Token::Eof {
+            return Err(ParseError::new(Expected::Token(Token::RBrace), token));
+        }
+        Ok(expression)
+    }
+
+    fn parse_prefix_operator(
+        &mut self,
+        operator: PrefixOperator,
+    ) -> Result<ExpressionKind, ParseError> {
+        let right = self.parse_expression(Precedence::Prefix)?;
+        Ok(ExpressionKind::Prefix {
+            operator,
+            right: Box::new(right),
+        })
+    }
+
+    fn parse_if_expression(&mut self) -> Result<ExpressionKind, ParseError> {
+        self.expect(Token::LParen)?;
+        let condition = self.parse_expression(Precedence::Lowest)?;
+        self.expect(Token::RParen)?;
+        let consequence = self.parse_block_statement()?;
+        let alternative = if *self.peek() == Token::Else {
+            self.next();
+            Some(self.parse_block_statement()?)
+        } else {
+            None
+        };
+        Ok(ExpressionKind::If {
+            condition: Box::new(condition),
+            consequence,
+            alternative,
+        })
+    }
+
+    fn parse_function_literal(&mut self) -> Result<ExpressionKind, ParseError> {
+        self.expect(Token::LParen)?;
+        let parameters = self.parse_function_parameters()?;
+        let body = self.parse_block_statement()?;
+        Ok(ExpressionKind::FunctionLiteral { parameters, body })
+    }
+
+    /// Parses a comma separated parameter list, assuming the `(` has already been consumed.
+    fn parse_function_parameters(&mut self) -> Result<Vec<Identifier>, ParseError> {
+        let mut parameters = Vec::new();
+        if *self.peek() == Token::RParen {
+            self.next();
+            return Ok(parameters);
+        }
+        loop {
+            parameters.push(self.parse_identifier()?);
+            if *self.peek() != Token::Comma {
+                break;
+            }
+            self.next();
+        }
+        self.expect(Token::RParen)?;
+        Ok(parameters)
+    }
+
+    fn parse_block_statement(&mut self) -> Result<BlockStatement, ParseError> {
+        let start = self.peek_spanned().span;
+        self.expect(Token::LBrace)?;
+        let mut statements = Vec::new();
+        while *self.peek() != Token::RBrace && *self.peek() != Token::Eof {
+            let depth = self.depth;
+            match self.parse_statement() {
+                Ok(statement) => statements.push(statement),
+                Err(error) => {
+                    self.errors.push(error);
+                    self.synchronize(depth);
+                }
+            }
+        }
+        self.expect(Token::RBrace)?;
+        Ok(BlockStatement {
+            statements,
+            span: self.span_from(start),
+        })
+    }
+
+    fn parse_infix(&mut self, left: Expression) -> Result<Expression, ParseError> {
+        let start = left.span;
         let token = self.next();
-        assert_eq!(token, Token::Semicolon);
-        Statement::Let(LetStatement {
-            variable,
-            expression,
+        if token.token == Token::LParen {
+            let arguments = self.parse_expression_list(Token::RParen)?;
+            return Ok(Expression {
+                kind: ExpressionKind::Call {
+                    function: Box::new(left),
+                    arguments,
+                },
+                span: self.span_from(start),
+            });
+        }
+        if token.token == Token::LBracket {
+            let index = self.parse_expression(Precedence::Lowest)?;
+            self.expect(Token::RBracket)?;
+            return Ok(Expression {
+                kind: ExpressionKind::Index {
+                    left: Box::new(left),
+                    index: Box::new(index),
+                },
+                span: self.span_from(start),
+            });
+        }
+        let operator = match InfixOperator::from_token(&token.token) {
+            Some(operator) => operator,
+            None => return Err(ParseError::new(Expected::Operator, token)),
+        };
+        let right = self.parse_expression(Precedence::of(&token.token))?;
+        Ok(Expression {
+            kind: ExpressionKind::Infix {
+                left: Box::new(left),
+                operator,
+                right: Box::new(right),
+            },
+            span: self.span_from(start),
         })
     }
 
-    fn parse_expression(&mut self) -> Expression {
-        match self.next() {
-            Token::Int(number) => Expression::IntegerLiteral(number.parse().unwrap()),
-            Token::Ident(ident) => Expression::IdentifierLiteral(ident),
-            _ => panic!("bad token {:?}", self.next()),
+    /// Parses comma separated expressions up to `end`, assuming the opening `(` or `[` has
+    /// already been consumed.
+    fn parse_expression_list(&mut self, end: OwnedToken) -> Result<Vec<Expression>, ParseError> {
+        let mut expressions = Vec::new();
+        if *self.peek() == end {
+            self.next();
+            return Ok(expressions);
         }
+        expressions.push(self.parse_expression(Precedence::Lowest)?);
+        while *self.peek() == Token::Comma {
+            self.next();
+            expressions.push(self.parse_expression(Precedence::Lowest)?);
+        }
+        self.expect(end)?;
+        Ok(expressions)
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+
+    fn span(start: usize, end: usize, line: usize, column: usize) -> Span {
+        Span {
+            start,
+            end,
+            line,
+            column,
+        }
+    }
+
     #[test]
     fn it_works() {
         let mut parser = Parser::new("let foo = 5;");
-        let statements = parser.parse();
-        let expected_statements = vec![Statement::Let(LetStatement {
-            variable: "foo".to_string(),
-            expression: Expression::IntegerLiteral(5),
-        })];
+        let statements = parser.parse().unwrap().statements;
+        let expected_statements = vec![Statement {
+            kind: StatementKind::Let(LetStatement {
+                variable: Identifier {
+                    name: "foo".into(),
+                    span: span(4, 7, 1, 5),
+                },
+                expression: Expression {
+                    kind: ExpressionKind::IntegerLiteral(5),
+                    span: span(10, 11, 1, 11),
+                },
+            }),
+            span: span(0, 12, 1, 1),
+        }];
         for (statement, expected_statements) in statements.into_iter().zip(expected_statements) {
             assert_eq!(statement, expected_statements);
         }
     }
+
+    fn parse_let_expression(input: &str) -> String {
+        let mut parser = Parser::new(input);
+        match parser.parse().unwrap().statements.pop().map(|s| s.kind) {
+            Some(StatementKind::Let(LetStatement { expression, .. })) => expression.to_string(),
+            statement => panic!("expected a let statement, got {:?}", statement),
+        }
+    }
+
+    /// Parses `input` and returns the `expected` and `found` parts of each error.
+    fn parse_errors(input: &str) -> Vec<(Expected, OwnedToken)> {
+        let errors = Parser::new(input).parse().unwrap_err();
+        errors.into_iter().map(|e| (e.expected, e.found)).collect()
+    }
+
+    #[test]
+    fn operator_precedence() {
+        let cases = [
+            ("let x = -a * b;", "((-a) * b)"),
+            ("let x = !-a;", "(!(-a))"),
+            ("let x = a + b - c;", "((a + b) - c)"),
+            ("let x = a + b * c - d / e;", "((a + (b * c)) - (d / e))"),
+            ("let x = 5 > 4 == 3 < 4;", "((5 > 4) == (3 < 4))"),
+            ("let x = 1 + (2 + 3) + 4;", "((1 + (2 + 3)) + 4)"),
+            ("let x = !(true == true);", "(!(true == true))"),
+            ("let x = (5 + 10) * -2 < y;", "(((5 + 10) * (-2)) < y)"),
+            ("let x = a + add(b * c) + d;", "((a + add((b * c))) + d)"),
+            (
+                "let x = add(a, b, 1, 2 * 3, add(6));",
+                "add(a, b, 1, (2 * 3), add(6))",
+            ),
+            ("let x = f();", "f()"),
+            (
+                "let x = a * [1, 2, 3, 4][b * c] * d;",
+                "((a * ([1, 2, 3, 4][(b * c)])) * d)",
+            ),
+            (
+                "let x = add(a * b[2], b[1], 2 * [1, 2][1]);",
+                "add((a * (b[2])), (b[1]), (2 * ([1, 2][1])))",
+            ),
+            ("let x = [];", "[]"),
+            ("let x = f(1)[0](2);", "(f(1)[0])(2)"),
+        ];
+        for (input, expected) in cases {
+            assert_eq!(parse_let_expression(input), expected);
+        }
+    }
+
+    #[test]
+    fn parses_every_statement() {
+        let mut parser = Parser::new("let x = 5; return x; x + 1\n!x");
+        let program = parser.parse().unwrap();
+        assert_eq!(program.statements.len(), 4);
+        assert_eq!(
+            program.to_string(),
+            "let x = 5;\nreturn x;\n(x + 1)\n(!x)\n"
+        );
+    }
+
+    #[test]
+    fn collects_errors_and_recovers() {
+        let input = "let = 5; let x 5; let y = 10; )\nlet z = 99999999999999999999;";
+        assert_eq!(
+            parse_errors(input),
+            vec![
+                (Expected::Identifier, Token::Assign),
+                (Expected::Token(Token::Assign), Token::Int("5".into())),
+                (Expected::Expression, Token::RParen),
+                (Expected::Integer, Token::Int("99999999999999999999".into())),
+            ]
+        );
+        let errors = Parser::new(input).parse().unwrap_err();
+        assert_eq!(errors[1].to_string(), "expected `=`, found `5`");
+        assert_eq!(errors[1].span, span(15, 16, 1, 16));
+        assert_eq!(errors[3].span, span(40, 60, 2, 9));
+    }
+
+    #[test]
+    fn recovers_within_blocks() {
+        assert_eq!(
+            parse_errors("fn(x) { let = 1; }"),
+            vec![(Expected::Identifier, Token::Assign)]
+        );
+        assert_eq!(
+            parse_errors("let = fn(x) { 1; 2 }; if (x) { ) } else { let y 2; }; 3"),
+            vec![
+                (Expected::Identifier, Token::Assign),
+                (Expected::Expression, Token::RParen),
+                (Expected::Token(Token::Assign), Token::Int("2".into())),
+            ]
+        );
+        assert_eq!(
+            parse_errors("fn() { let h = {1, 2}; 5 }; 6"),
+            vec![(Expected::Token(Token::Colon), Token::Comma)]
+        );
+        assert_eq!(
+            parse_errors(r#""${fn() { let = 1; }}""#),
+            vec![(Expected::Identifier, Token::Assign)]
+        );
+    }
+
+    #[test]
+    fn limits_nesting() {
+        let nested = |depth: usize| format!("{}1{}", "(".repeat(depth), ")".repeat(depth));
+        // The statement's expression is the first level.
+        assert!(Parser::new(&nested(MAX_NESTING - 1)).parse().is_ok());
+        let input = format!("{}; let x = 2; {}", nested(100_000), nested(MAX_NESTING));
+        assert_eq!(
+            parse_errors(&input),
+            vec![
+                (Expected::LessNesting, Token::LParen),
+                (Expected::LessNesting, Token::Int("1".into())),
+            ]
+        );
+        let errors = Parser::new(&input).parse().unwrap_err();
+        assert_eq!(
+            errors[0].to_string(),
+            "expression nested too deeply, the limit is 128"
+        );
+        assert_eq!(errors[0].span, span(128, 129, 1, 129));
+
+        let input = format!(r#""${{{}}}""#, nested(MAX_NESTING - 1));
+        assert_eq!(
+            parse_errors(&input),
+            vec![(Expected::LessNesting, Token::Int("1".into()))]
+        );
+    }
+
+    #[test]
+    fn reports_unexpected_end_of_input() {
+        let errors = Parser::new("add(1, 2").parse().unwrap_err();
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].found, Token::Eof);
+        assert_eq!(errors[0].span, span(8, 8, 1, 9));
+        assert_eq!(errors[0].to_string(), "expected `)`, found end of input");
+
+        let errors = Parser::new("f(\"a\nbc\"  \n").parse().unwrap_err();
+        assert_eq!(errors[0].span, span(8, 8, 2, 4));
+    }
+
+    #[test]
+    fn function_literals() {
+        let cases = [
+            ("let f = fn() { };", "fn() { }"),
+            ("let f = fn(x) { x };", "fn(x) { x }"),
+            (
+                "let add = fn(x, y) { let z = x + y; return z; };",
+                "fn(x, y) { let z = (x + y); return z; }",
+            ),
+            ("let r = fn(x) { x }(5);", "fn(x) { x }(5)"),
+        ];
+        for (input, expected) in cases {
+            assert_eq!(parse_let_expression(input), expected);
+        }
+        assert_eq!(
+            parse_errors("fn(x, 1) { x }"),
+            vec![(Expected::Identifier, Token::Int("1".into()))]
+        );
+    }
+
+    #[test]
+    fn if_expressions() {
+        let cases = [
+            ("let r = if (x < y) { x };", "if (x < y) { x }"),
+            (
+                "let r = if (x) { x } else { let z = 1; y };",
+                "if x { x } else { let z = 1; y }",
+            ),
+            (
+                "let r = if (a) { if (b) { return 1; } } else { 2 };",
+                "if a { if b { return 1; } } else { 2 }",
+            ),
+        ];
+        for (input, expected) in cases {
+            assert_eq!(parse_let_expression(input), expected);
+        }
+        assert_eq!(
+            parse_errors("if x { 1 }")[0],
+            (Expected::Token(Token::LParen), Token::Ident("x".into()))
+        );
+    }
+
+    #[test]
+    fn expression_spans() {
+        let program = Parser::new("let a = 1;\nfoo(a + 2, -b)").parse().unwrap();
+        assert_eq!(program.span, span(0, 25, 1, 1));
+        let call = match &program.statements[1].kind {
+            StatementKind::Expression(expression) => expression,
+            statement => panic!("expected an expression statement, got {:?}", statement),
+        };
+        assert_eq!(call.span, span(11, 25, 2, 1));
+        let ExpressionKind::Call { arguments, .. } = &call.kind else {
+            panic!("expected a call, got {:?}", call.kind);
+        };
+        assert_eq!(arguments[0].span, span(15, 20, 2, 5));
+        assert_eq!(arguments[1].span, span(22, 24, 2, 12));
+    }
+
+    #[test]
+    fn hash_literals() {
+        let cases = [
+            ("let h = {};", "{}"),
+            (
+                r#"let h = {"one": 1, two: 1 + 1, 3: [3]};"#,
+                r#"{"one": 1, two: (1 + 1), 3: [3]}"#,
+            ),
+            (
+                r#"let h = {"a": {1: 2}}["a"][1];"#,
+                r#"(({"a": {1: 2}}["a"])[1])"#,
+            ),
+        ];
+        for (input, expected) in cases {
+            assert_eq!(parse_let_expression(input), expected);
+        }
+        assert_eq!(
+            parse_errors("let h = {1, 2};"),
+            vec![(Expected::Token(Token::Colon), Token::Comma)]
+        );
+        assert_eq!(
+            parse_errors("let h = {1: 2 3: 4};"),
+            vec![(Expected::Token(Token::Comma), Token::Int("3".into()))]
+        );
+    }
+
+    #[test]
+    fn string_literals() {
+        assert_eq!(
+            parse_let_expression(r#"let s = "a\tb" + "c";"#),
+            r#"("a\tb" + "c")"#
+        );
+        assert_eq!(
+            parse_let_expression(r#"let f = fn() { "\${x}\u{d}" };"#),
+            r#"fn() { "\${x}\u{d}" }"#
+        );
+        let errors = Parser::new("let s = \"abc;").parse().unwrap_err();
+        assert_eq!(errors[0].to_string(), "unterminated string");
+        assert_eq!((errors[0].span.start, errors[0].span.end), (8, 9));
+        let errors = Parser::new(r#"let s = "\x";"#).parse().unwrap_err();
+        assert_eq!(errors[0].to_string(), r"invalid escape sequence `\x`");
+    }
+
+    #[test]
+    fn interpolated_strings() {
+        assert_eq!(
+            parse_let_expression(r#"let s = "hello ${name}, you are ${age + 1}\n";"#),
+            r#""hello ${name}, you are ${(age + 1)}\n""#
+        );
+
+        let program = Parser::new(r#"let s = "a${x * 2}";"#).parse().unwrap();
+        let StatementKind::Let(LetStatement { expression, .. }) = &program.statements[0].kind
+        else {
+            panic!("expected a let statement");
+        };
+        let ExpressionKind::InterpolatedString(parts) = &expression.kind else {
+            panic!("expected an interpolated string, got {:?}", expression.kind);
+        };
+        let InterpolationPart::Expression(embedded) = &parts[1] else {
+            panic!("expected an embedded expression, got {:?}", parts[1]);
+        };
+        assert_eq!(embedded.span, span(12, 17, 1, 13));
+
+        let errors = Parser::new(r#""${1 +}""#).parse().unwrap_err();
+        assert_eq!(errors[0].to_string(), "expected an expression, found `}`");
+        assert_eq!(errors[0].span.start, 6);
+        let errors = Parser::new(r#""${a b}""#).parse().unwrap_err();
+        assert_eq!(errors[0].to_string(), "expected `}`, found `b`");
+        assert_eq!(errors[0].span.start, 5);
+    }
 }
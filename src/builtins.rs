@@ -0,0 +1,267 @@
+use crate::eval::{EvalErrorKind, Object};
+use std::collections::HashMap;
+use std::fmt;
+use std::rc::Rc;
+
+/// The signature of a function implemented in Rust.
+pub type NativeFunction = dyn Fn(Vec<Object>) -> Result<Object, EvalErrorKind>;
+
+/// How many arguments a builtin accepts.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Arity {
+    Exactly(usize),
+    AtLeast(usize),
+}
+
+/// A function implemented in Rust and callable from Monkey.
+pub struct Builtin {
+    name: String,
+    arity: Arity,
+    function: Box<NativeFunction>,
+}
+
+impl Builtin {
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Checks the number of arguments, then calls the function.
+    pub fn call(&self, arguments: Vec<Object>) -> Result<Object, EvalErrorKind> {
+        match self.arity {
+            Arity::Exactly(expected) if arguments.len() != expected => {
+                Err(EvalErrorKind::WrongArgumentCount {
+                    expected,
+                    found: arguments.len(),
+                })
+            }
+            Arity::AtLeast(minimum) if arguments.len() < minimum => {
+                Err(EvalErrorKind::TooFewArguments {
+                    minimum,
+                    found: arguments.len(),
+                })
+            }
+            _ => (self.function)(arguments),
+        }
+    }
+}
+
+impl fmt::Display for Builtin {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "builtin function {}", self.name)
+    }
+}
+
+impl fmt::Debug for Builtin {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Builtin")
+            .field("name", &self.name)
+            .field("arity", &self.arity)
+            .finish_non_exhaustive()
+    }
+}
+
+/// Builtins are compared by identity, like functions.
+impl PartialEq for Builtin {
+    fn eq(&self, other: &Self) -> bool {
+        std::ptr::eq(self, other)
+    }
+}
+
+impl Eq for Builtin {}
+
+/// The builtins an environment falls back to when a name isn't bound.
+///
+/// ```
+/// use interpreter::builtins::{Arity, Builtins};
+/// use interpreter::eval::{EvalErrorKind, Object};
+///
+/// let mut builtins = Builtins::standard();
+/// builtins.register("double", Arity::Exactly(1), |arguments| match &arguments[0] {
+///     Object::Integer(value) => Ok(Object::Integer(value * 2)),
+///     other => Err(EvalErrorKind::Custom(format!("cannot double {}", other.type_name()))),
+/// });
+/// ```
+pub struct Builtins {
+    functions: HashMap<String, Rc<Builtin>>,
+}
+
+impl Builtins {
+    /// A registry without any builtins.
+    pub fn empty() -> Self {
+        Self {
+            functions: HashMap::new(),
+        }
+    }
+
+    /// A registry with the builtins every Monkey program can use.
+    pub fn standard() -> Self {
+        let mut builtins = Self::empty();
+        builtins.register("len", Arity::Exactly(1), len);
+        builtins.register("first", Arity::Exactly(1), first);
+        builtins.register("last", Arity::Exactly(1), last);
+        builtins.register("rest", Arity::Exactly(1), rest);
+        builtins.register("push", Arity::Exactly(2), push);
+        builtins.register("puts", Arity::AtLeast(0), puts);
+        builtins.register("type", Arity::Exactly(1), type_of);
+        builtins.register("str", Arity::Exactly(1), str);
+        builtins.register("int", Arity::Exactly(1), int);
+        builtins
+    }
+
+    /// Makes `function` callable as `name`, replacing any builtin of the same name. Calls with a
+    /// number of arguments that doesn't match `arity` fail before `function` is invoked.
+    pub fn register<F>(&mut self, name: &str, arity: Arity, function: F)
+    where
+        F: Fn(Vec<Object>) -> Result<Object, EvalErrorKind> + 'static,
+    {
+        let builtin = Builtin {
+            name: name.to_string(),
+            arity,
+            function: Box::new(function),
+        };
+        self.functions.insert(name.to_string(), Rc::new(builtin));
+    }
+
+    pub fn get(&self, name: &str) -> Option<Object> {
+        self.functions.get(name).cloned().map(Object::Builtin)
+    }
+
+    /// Returns the names of all builtins, sorted.
+    pub fn names(&self) -> Vec<&str> {
+        let mut names: Vec<&str> = self.functions.keys().map(String::as_str).collect();
+        names.sort_unstable();
+        names
+    }
+}
+
+impl Default for Builtins {
+    fn default() -> Self {
+        Self::standard()
+    }
+}
+
+impl fmt::Debug for Builtins {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_set().entries(self.names()).finish()
+    }
+}
+
+fn unsupported(function: &'static str, argument: &Object) -> EvalErrorKind {
+    EvalErrorKind::UnsupportedArgument {
+        function,
+        found: argument.type_name(),
+    }
+}
+
+/// The number of elements of an array or hash, or of characters in a string.
+fn len(arguments: Vec<Object>) -> Result<Object, EvalErrorKind> {
+    match &arguments[0] {
+        Object::String(value) => Ok(Object::Integer(value.chars().count() as i64)),
+        Object::Array(elements) => Ok(Object::Integer(elements.len() as i64)),
+        Object::Hash(pairs) => Ok(Object::Integer(pairs.len() as i64)),
+        other => Err(unsupported("len", other)),
+    }
+}
+
+/// The first element of an array, or `null` if it is empty.
+fn first(arguments: Vec<Object>) -> Result<Object, EvalErrorKind> {
+    match &arguments[0] {
+        Object::Array(elements) => Ok(elements.first().cloned().unwrap_or(Object::Null)),
+        other => Err(unsupported("first", other)),
+    }
+}
+
+/// The last element of an array, or `null` if it is empty.
+fn last(arguments: Vec<Object>) -> Result<Object, EvalErrorKind> {
+    match &arguments[0] {
+        Object::Array(elements) => Ok(elements.last().cloned().unwrap_or(Object::Null)),
+        other => Err(unsupported("last", other)),
+    }
+}
+
+/// A new array with every element but the first, or `null` if the array is empty.
+fn rest(arguments: Vec<Object>) -> Result<Object, EvalErrorKind> {
+    match &arguments[0] {
+        Object::Array(elements) if elements.is_empty() => Ok(Object::Null),
+        Object::Array(elements) => Ok(Object::Array(elements[1..].to_vec())),
+        other => Err(unsupported("rest", other)),
+    }
+}
+
+/// A new array with the second argument appended; the original array is left as is.
+fn push(arguments: Vec<Object>) -> Result<Object, EvalErrorKind> {
+    match &arguments[0] {
+        Object::Array(elements) => {
+            let mut elements = elements.clone();
+            elements.push(arguments[1].clone());
+            Ok(Object::Array(elements))
+        }
+        other => Err(unsupported("push", other)),
+    }
+}
+
+/// Prints each argument on its own line.
+fn puts(arguments: Vec<Object>) -> Result<Object, EvalErrorKind> {
+    for argument in arguments {
+        println!("{}", argument);
+    }
+    Ok(Object::Null)
+}
+
+/// The name of the argument's type, e.g. `"INTEGER"`.
+fn type_of(arguments: Vec<Object>) -> Result<Object, EvalErrorKind> {
+    Ok(Object::String(arguments[0].type_name().to_string()))
+}
+
+/// The argument as it would be printed.
+fn str(arguments: Vec<Object>) -> Result<Object, EvalErrorKind> {
+    Ok(Object::String(arguments[0].to_string()))
+}
+
+/// Parses a string as a decimal integer; integers are returned as is.
+fn int(arguments: Vec<Object>) -> Result<Object, EvalErrorKind> {
+    match &arguments[0] {
+        Object::Integer(value) => Ok(Object::Integer(*value)),
+        Object::String(text) => match text.trim().parse() {
+            Ok(value) => Ok(Object::Integer(value)),
+            Err(_) => Err(EvalErrorKind::Custom(format!(
+                "could not convert {:?} to an integer",
+                text
+            ))),
+        },
+        other => Err(unsupported("int", other)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn arity_is_checked_before_calling() {
+        let mut builtins = Builtins::empty();
+        builtins.register("answer", Arity::Exactly(0), |_| Ok(Object::Integer(42)));
+        builtins.register("count", Arity::AtLeast(1), |arguments| {
+            Ok(Object::Integer(arguments.len() as i64))
+        });
+        assert_eq!(builtins.names(), ["answer", "count"]);
+
+        let Some(Object::Builtin(answer)) = builtins.get("answer") else {
+            panic!("expected a builtin");
+        };
+        assert_eq!(answer.call(vec![]), Ok(Object::Integer(42)));
+        assert_eq!(
+            answer.call(vec![Object::Null]).unwrap_err().to_string(),
+            "wrong number of arguments: expected 0, found 1"
+        );
+        let Some(Object::Builtin(count)) = builtins.get("count") else {
+            panic!("expected a builtin");
+        };
+        assert_eq!(count.call(vec![Object::Null; 3]), Ok(Object::Integer(3)));
+        assert_eq!(
+            count.call(vec![]).unwrap_err().to_string(),
+            "wrong number of arguments: expected at least 1, found 0"
+        );
+        assert_eq!(builtins.get("len"), None);
+    }
+}
@@ -0,0 +1,317 @@
+use crate::token::Span;
+use std::fmt;
+
+/// A single bytecode instruction, without its operands.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+#[repr(u8)]
+pub enum Opcode {
+    /// Pushes the constant at the index given by the operand.
+    Constant,
+    Pop,
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Equal,
+    NotEqual,
+    LessThan,
+    GreaterThan,
+    Minus,
+    Bang,
+    True,
+    False,
+    Null,
+    /// Pops the condition and jumps to the operand's offset if it is falsy.
+    JumpNotTruthy,
+    Jump,
+    GetGlobal,
+    SetGlobal,
+    /// Pushes the value of a slot in the frame of the current call.
+    GetLocal,
+    SetLocal,
+    /// Pushes the value of a slot in the frame of an enclosing function, given by how many
+    /// functions out it is and the slot.
+    GetOuter,
+    /// Collects the given number of values into an array.
+    Array,
+    /// Collects the given number of key-value pairs into a hash.
+    Hash,
+    /// Checks that the value on top of the stack can be used as a hash key, leaving it in place.
+    HashKey,
+    Index,
+    /// Concatenates the given number of values as they would be printed.
+    Interpolate,
+    /// Calls the function below the given number of arguments.
+    Call,
+    ReturnValue,
+    /// Wraps the function constant given by the operand into a closure over the frame of the
+    /// current call.
+    Closure,
+}
+
+const OPCODES: [Opcode; 30] = [
+    Opcode::Constant,
+    Opcode::Pop,
+    Opcode::Add,
+    Opcode::Sub,
+    Opcode::Mul,
+    Opcode::Div,
+    Opcode::Equal,
+    Opcode::NotEqual,
+    Opcode::LessThan,
+    Opcode::GreaterThan,
+    Opcode::Minus,
+    Opcode::Bang,
+    Opcode::True,
+    Opcode::False,
+    Opcode::Null,
+    Opcode::JumpNotTruthy,
+    Opcode::Jump,
+    Opcode::GetGlobal,
+    Opcode::SetGlobal,
+    Opcode::GetLocal,
+    Opcode::SetLocal,
+    Opcode::GetOuter,
+    Opcode::Array,
+    Opcode::Hash,
+    Opcode::HashKey,
+    Opcode::Index,
+    Opcode::Interpolate,
+    Opcode::Call,
+    Opcode::ReturnValue,
+    Opcode::Closure,
+];
+
+impl Opcode {
+    /// Returns the width in bytes of each of the instruction's operands. Operands are big-endian.
+    pub fn operand_widths(self) -> &'static [usize] {
+        match self {
+            Opcode::Constant
+            | Opcode::JumpNotTruthy
+            | Opcode::Jump
+            | Opcode::GetGlobal
+            | Opcode::SetGlobal
+            | Opcode::Array
+            | Opcode::Hash
+            | Opcode::Interpolate
+            | Opcode::Closure => &[2],
+            Opcode::GetLocal | Opcode::SetLocal | Opcode::Call => &[1],
+            Opcode::GetOuter => &[1, 1],
+            _ => &[],
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Opcode::Constant => "OpConstant",
+            Opcode::Pop => "OpPop",
+            Opcode::Add => "OpAdd",
+            Opcode::Sub => "OpSub",
+            Opcode::Mul => "OpMul",
+            Opcode::Div => "OpDiv",
+            Opcode::Equal => "OpEqual",
+            Opcode::NotEqual => "OpNotEqual",
+            Opcode::LessThan => "OpLessThan",
+            Opcode::GreaterThan => "OpGreaterThan",
+            Opcode::Minus => "OpMinus",
+            Opcode::Bang => "OpBang",
+            Opcode::True => "OpTrue",
+            Opcode::False => "OpFalse",
+            Opcode::Null => "OpNull",
+            Opcode::JumpNotTruthy => "OpJumpNotTruthy",
+            Opcode::Jump => "OpJump",
+            Opcode::GetGlobal => "OpGetGlobal",
+            Opcode::SetGlobal => "OpSetGlobal",
+            Opcode::GetLocal => "OpGetLocal",
+            Opcode::SetLocal => "OpSetLocal",
+            Opcode::GetOuter => "OpGetOuter",
+            Opcode::Array => "OpArray",
+            Opcode::Hash => "OpHash",
+            Opcode::HashKey => "OpHashKey",
+            Opcode::Index => "OpIndex",
+            Opcode::Interpolate => "OpInterpolate",
+            Opcode::Call => "OpCall",
+            Opcode::ReturnValue => "OpReturnValue",
+            Opcode::Closure => "OpClosure",
+        }
+    }
+}
+
+impl TryFrom<u8> for Opcode {
+    type Error = u8;
+
+    fn try_from(byte: u8) -> Result<Self, Self::Error> {
+        OPCODES.get(byte as usize).copied().ok_or(byte)
+    }
+}
+
+/// Encodes an instruction. Operands that don't fit their width are truncated, so callers check
+/// them against the limits first.
+pub fn make(opcode: Opcode, operands: &[usize]) -> Vec<u8> {
+    let widths = opcode.operand_widths();
+    let mut instruction = Vec::with_capacity(1 + widths.iter().sum::<usize>());
+    instruction.push(opcode as u8);
+    for (&operand, &width) in operands.iter().zip(widths) {
+        match width {
+            2 => instruction.extend_from_slice(&(operand as u16).to_be_bytes()),
+            _ => instruction.push(operand as u8),
+        }
+    }
+    instruction
+}
+
+/// Decodes the operands of `opcode` from the start of `bytes`, returning them along with the
+/// number of bytes they took up.
+pub fn read_operands(opcode: Opcode, bytes: &[u8]) -> (Vec<usize>, usize) {
+    let mut operands = Vec::new();
+    let mut offset = 0;
+    for &width in opcode.operand_widths() {
+        match width {
+            2 => operands.push(read_u16(bytes, offset)),
+            _ => operands.push(bytes[offset] as usize),
+        }
+        offset += width;
+    }
+    (operands, offset)
+}
+
+pub fn read_u16(bytes: &[u8], offset: usize) -> usize {
+    u16::from_be_bytes([bytes[offset], bytes[offset + 1]]) as usize
+}
+
+/// Wraps encoded instructions to print them one per line, e.g. `0003 OpConstant 1`.
+pub struct Disassembly<'a>(pub &'a [u8]);
+
+impl fmt::Display for Disassembly<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut offset = 0;
+        while offset < self.0.len() {
+            let Ok(opcode) = Opcode::try_from(self.0[offset]) else {
+                writeln!(f, "{:04} <unknown opcode {}>", offset, self.0[offset])?;
+                offset += 1;
+                continue;
+            };
+            let (operands, read) = read_operands(opcode, &self.0[offset + 1..]);
+            write!(f, "{:04} {}", offset, opcode.name())?;
+            for operand in operands {
+                write!(f, " {}", operand)?;
+            }
+            writeln!(f)?;
+            offset += 1 + read;
+        }
+        Ok(())
+    }
+}
+
+/// What the name of a local slot refers to while the slot isn't bound yet, resolved by the
+/// compiler.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Fallback {
+    /// A slot of an enclosing function, `depth` functions out, which falls back in turn if it
+    /// isn't bound either.
+    Outer { depth: usize, index: usize },
+    /// A global, or the builtin of its name if the global isn't set.
+    Global(usize),
+}
+
+/// The bytecode of a function, or of the top level of a program.
+#[derive(Debug, PartialEq, Eq, Clone, Default)]
+pub struct CompiledFunction {
+    pub instructions: Vec<u8>,
+    /// The names of the local slots, parameters first.
+    pub locals: Vec<String>,
+    pub num_parameters: usize,
+    /// What the slots after the parameters, which `let` statements bind, refer to until they are
+    /// bound, as their names refer to what they do outside of the function until then.
+    pub fallbacks: Vec<Fallback>,
+    /// The source spans of the instructions that can fail, ordered by offset.
+    pub spans: Vec<(usize, Span)>,
+    /// The offsets at which the code of each source line starts, with the line, ordered by
+    /// offset.
+    pub lines: Vec<(usize, usize)>,
+    /// The function as written, e.g. `fn(x) { (x + 1) }`, so that it prints like the evaluator's
+    /// functions.
+    pub source: String,
+}
+
+impl CompiledFunction {
+    /// Returns the span of the instruction at `offset`, if it was recorded.
+    pub fn span_at(&self, offset: usize) -> Option<Span> {
+        let index = self
+            .spans
+            .binary_search_by_key(&offset, |&(offset, _)| offset)
+            .ok()?;
+        Some(self.spans[index].1)
+    }
+
+    /// Returns the source line that the instruction at `offset` was compiled from.
+    pub fn line_at(&self, offset: usize) -> Option<usize> {
+        let index = self.lines.partition_point(|&(start, _)| start <= offset);
+        Some(self.lines.get(index.checked_sub(1)?)?.1)
+    }
+}
+
+impl fmt::Display for CompiledFunction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.source)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn encodes_and_decodes_operands() {
+        let cases: [(Opcode, &[usize], &[u8]); 4] = [
+            (
+                Opcode::Constant,
+                &[65534],
+                &[Opcode::Constant as u8, 255, 254],
+            ),
+            (Opcode::Add, &[], &[Opcode::Add as u8]),
+            (Opcode::GetLocal, &[255], &[Opcode::GetLocal as u8, 255]),
+            (
+                Opcode::GetOuter,
+                &[2, 255],
+                &[Opcode::GetOuter as u8, 2, 255],
+            ),
+        ];
+        for (opcode, operands, expected) in cases {
+            let instruction = make(opcode, operands);
+            assert_eq!(instruction, expected);
+            assert_eq!(Opcode::try_from(instruction[0]), Ok(opcode));
+            let (read, width) = read_operands(opcode, &instruction[1..]);
+            assert_eq!(read, operands);
+            assert_eq!(width, instruction.len() - 1);
+        }
+        for opcode in OPCODES {
+            assert_eq!(Opcode::try_from(opcode as u8), Ok(opcode));
+        }
+        assert_eq!(
+            Opcode::try_from(OPCODES.len() as u8),
+            Err(OPCODES.len() as u8)
+        );
+    }
+
+    #[test]
+    fn disassembles_instructions() {
+        let instructions = [
+            make(Opcode::Add, &[]),
+            make(Opcode::GetLocal, &[1]),
+            make(Opcode::Constant, &[2]),
+            make(Opcode::Constant, &[65535]),
+            make(Opcode::GetOuter, &[1, 255]),
+        ]
+        .concat();
+        assert_eq!(
+            Disassembly(&instructions).to_string(),
+            "0000 OpAdd
+0001 OpGetLocal 1
+0003 OpConstant 2
+0006 OpConstant 65535
+0009 OpGetOuter 1 255
+"
+        );
+    }
+}
@@ -0,0 +1,662 @@
+use crate::ast::{
+    BlockStatement, Expression, ExpressionKind, Identifier, InfixOperator, InterpolationPart,
+    LetStatement, PrefixOperator, Program, Statement, StatementKind,
+};
+use crate::code::{self, CompiledFunction, Fallback, Opcode};
+use crate::eval::Object;
+use crate::token::Span;
+use std::collections::HashMap;
+use std::fmt;
+use std::rc::Rc;
+use symbol_table::{Symbol, SymbolTable};
+
+mod symbol_table;
+
+/// The output of the compiler: the program's top level and everything it refers to.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct Bytecode {
+    pub main: CompiledFunction,
+    pub constants: Vec<Object>,
+    /// The names of the global slots, used to look up builtins and to report unbound names.
+    pub globals: Vec<String>,
+}
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum CompileErrorKind {
+    /// The program needs more of something than the bytecode format can address.
+    TooMany { what: &'static str, limit: usize },
+}
+
+impl fmt::Display for CompileErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CompileErrorKind::TooMany { what, limit } => {
+                write!(f, "too many {}, the limit is {}", what, limit)
+            }
+        }
+    }
+}
+
+/// A compile error, located at the construct that couldn't be compiled.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct CompileError {
+    pub kind: CompileErrorKind,
+    pub span: Span,
+}
+
+impl fmt::Display for CompileError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.kind)
+    }
+}
+
+impl std::error::Error for CompileError {}
+
+const MAX_U8: usize = u8::MAX as usize;
+const MAX_U16: usize = u16::MAX as usize;
+
+/// Compiles `program` into bytecode for the `vm`.
+pub fn compile(program: &Program) -> Result<Bytecode, CompileError> {
+    let mut compiler = Compiler::default();
+    compiler.compile_block(&program.statements)?;
+    compiler.emit(Opcode::ReturnValue, &[]);
+    let main = compiler.scopes.pop().unwrap_or_default();
+    Ok(Bytecode {
+        main: CompiledFunction {
+            instructions: main.instructions,
+            spans: main.spans,
+            lines: main.lines,
+            ..CompiledFunction::default()
+        },
+        constants: compiler.constants,
+        globals: compiler.global_names,
+    })
+}
+
+/// The instructions of the function being compiled.
+#[derive(Debug, Default)]
+struct Scope {
+    instructions: Vec<u8>,
+    spans: Vec<(usize, Span)>,
+    lines: Vec<(usize, usize)>,
+}
+
+#[derive(Debug)]
+struct Compiler {
+    constants: Vec<Object>,
+    globals: HashMap<String, usize>,
+    global_names: Vec<String>,
+    /// The top level followed by the functions being compiled, innermost last.
+    scopes: Vec<Scope>,
+    /// The symbols of the functions being compiled; the top level has none, as its names are
+    /// globals.
+    tables: Vec<SymbolTable>,
+}
+
+impl Default for Compiler {
+    fn default() -> Self {
+        Self {
+            constants: Vec::new(),
+            globals: HashMap::new(),
+            global_names: Vec::new(),
+            scopes: vec![Scope::default()],
+            tables: Vec::new(),
+        }
+    }
+}
+
+fn too_many(what: &'static str, limit: usize, span: Span) -> CompileError {
+    CompileError {
+        kind: CompileErrorKind::TooMany { what, limit },
+        span,
+    }
+}
+
+impl Compiler {
+    fn scope(&mut self) -> &mut Scope {
+        self.scopes
+            .last_mut()
+            .expect("the top level scope is never popped")
+    }
+
+    /// Appends an instruction to the current scope and returns its offset.
+    fn emit(&mut self, opcode: Opcode, operands: &[usize]) -> usize {
+        let scope = self.scope();
+        let offset = scope.instructions.len();
+        scope.instructions.extend(code::make(opcode, operands));
+        offset
+    }
+
+    /// Appends an instruction that can fail at runtime, recording `span` to report the error at.
+    fn emit_at(&mut self, span: Span, opcode: Opcode, operands: &[usize]) -> usize {
+        let offset = self.emit(opcode, operands);
+        self.scope().spans.push((offset, span));
+        offset
+    }
+
+    /// Records that the code emitted next was compiled from `line`.
+    fn mark_line(&mut self, line: usize) {
+        let scope = self.scope();
+        let offset = scope.instructions.len();
+        match scope.lines.last_mut() {
+            Some((_, last)) if *last == line => {}
+            Some((start, last)) if *start == offset => *last = line,
+            _ => scope.lines.push((offset, line)),
+        }
+    }
+
+    /// Points the jump at `offset` to the next instruction.
+    fn patch_jump(&mut self, offset: usize, span: Span) -> Result<(), CompileError> {
+        let target = self.scope().instructions.len();
+        if target > MAX_U16 {
+            return Err(too_many("instructions in a function", MAX_U16, span));
+        }
+        let bytes = (target as u16).to_be_bytes();
+        self.scope().instructions[offset + 1..offset + 3].copy_from_slice(&bytes);
+        Ok(())
+    }
+
+    fn add_constant(&mut self, constant: Object, span: Span) -> Result<usize, CompileError> {
+        if self.constants.len() > MAX_U16 {
+            return Err(too_many("constants", MAX_U16 + 1, span));
+        }
+        self.constants.push(constant);
+        Ok(self.constants.len() - 1)
+    }
+
+    fn emit_constant(&mut self, constant: Object, span: Span) -> Result<(), CompileError> {
+        let index = self.add_constant(constant, span)?;
+        self.emit(Opcode::Constant, &[index]);
+        Ok(())
+    }
+
+    /// Returns the slot of the global `name`, allocating one on first use.
+    fn global(&mut self, name: &str, span: Span) -> Result<usize, CompileError> {
+        if let Some(&index) = self.globals.get(name) {
+            return Ok(index);
+        }
+        if self.global_names.len() > MAX_U16 {
+            return Err(too_many("global names", MAX_U16 + 1, span));
+        }
+        self.global_names.push(name.to_string());
+        self.globals
+            .insert(name.to_string(), self.global_names.len() - 1);
+        Ok(self.global_names.len() - 1)
+    }
+
+    /// Checks that a count fits the operand of the instruction it is emitted with.
+    fn check_count(
+        what: &'static str,
+        count: usize,
+        limit: usize,
+        span: Span,
+    ) -> Result<(), CompileError> {
+        if count > limit {
+            return Err(too_many(what, limit, span));
+        }
+        Ok(())
+    }
+
+    /// Compiles `statements` so that they leave exactly one value on the stack, like the
+    /// evaluator: the value of the last statement, or `null` for a `let` or an empty block.
+    fn compile_block(&mut self, statements: &[Statement]) -> Result<(), CompileError> {
+        if statements.is_empty() {
+            self.emit(Opcode::Null, &[]);
+            return Ok(());
+        }
+        for (i, statement) in statements.iter().enumerate() {
+            let last = i == statements.len() - 1;
+            self.compile_statement(statement)?;
+            match statement.kind {
+                StatementKind::Expression(_) if !last => {
+                    self.emit(Opcode::Pop, &[]);
+                }
+                StatementKind::Let(_) if last => {
+                    self.emit(Opcode::Null, &[]);
+                }
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+
+    fn compile_statement(&mut self, statement: &Statement) -> Result<(), CompileError> {
+        self.mark_line(statement.span.line);
+        match &statement.kind {
+            StatementKind::Let(let_statement) => self.compile_let(let_statement),
+            StatementKind::Return(expression) => {
+                self.compile_expression(expression)?;
+                self.emit(Opcode::ReturnValue, &[]);
+                Ok(())
+            }
+            StatementKind::Expression(expression) => self.compile_expression(expression),
+        }
+    }
+
+    fn compile_let(&mut self, statement: &LetStatement) -> Result<(), CompileError> {
+        let LetStatement {
+            variable,
+            expression,
+        } = statement;
+        self.compile_expression(expression)?;
+        match self.tables.last_mut() {
+            None => {
+                let index = self.global(&variable.name, variable.span)?;
+                self.emit(Opcode::SetGlobal, &[index]);
+            }
+            Some(table) => {
+                let index = table.define(&variable.name);
+                self.emit(Opcode::SetLocal, &[index]);
+            }
+        }
+        Ok(())
+    }
+
+    fn compile_expression(&mut self, expression: &Expression) -> Result<(), CompileError> {
+        let span = expression.span;
+        match &expression.kind {
+            ExpressionKind::IntegerLiteral(value) => {
+                self.emit_constant(Object::Integer(*value), span)?
+            }
+            ExpressionKind::BooleanLiteral(true) => {
+                self.emit(Opcode::True, &[]);
+            }
+            ExpressionKind::BooleanLiteral(false) => {
+                self.emit(Opcode::False, &[]);
+            }
+            ExpressionKind::StringLiteral(value) => {
+                self.emit_constant(Object::String(value.clone()), span)?
+            }
+            ExpressionKind::InterpolatedString(parts) => {
+                Self::check_count("interpolated parts", parts.len(), MAX_U16, span)?;
+                for part in parts {
+                    match part {
+                        InterpolationPart::Literal(text) => {
+                            self.emit_constant(Object::String(text.clone()), span)?
+                        }
+                        InterpolationPart::Expression(expression) => {
+                            self.compile_expression(expression)?
+                        }
+                    }
+                }
+                self.emit(Opcode::Interpolate, &[parts.len()]);
+            }
+            ExpressionKind::IdentifierLiteral(name) => self.compile_identifier(name, span)?,
+            ExpressionKind::Prefix { operator, right } => {
+                self.compile_expression(right)?;
+                let opcode = match operator {
+                    PrefixOperator::Bang => Opcode::Bang,
+                    PrefixOperator::Minus => Opcode::Minus,
+                };
+                self.emit_at(span, opcode, &[]);
+            }
+            ExpressionKind::Infix {
+                left,
+                operator,
+                right,
+            } => {
+                self.compile_expression(left)?;
+                self.compile_expression(right)?;
+                let opcode = match operator {
+                    InfixOperator::Plus => Opcode::Add,
+                    InfixOperator::Minus => Opcode::Sub,
+                    InfixOperator::Asterisk => Opcode::Mul,
+                    InfixOperator::Slash => Opcode::Div,
+                    InfixOperator::LessThan => Opcode::LessThan,
+                    InfixOperator::GreaterThan => Opcode::GreaterThan,
+                    InfixOperator::Equal => Opcode::Equal,
+                    InfixOperator::NotEqual => Opcode::NotEqual,
+                };
+                self.emit_at(span, opcode, &[]);
+            }
+            ExpressionKind::If {
+                condition,
+                consequence,
+                alternative,
+            } => {
+                self.compile_expression(condition)?;
+                let jump_not_truthy = self.emit(Opcode::JumpNotTruthy, &[MAX_U16]);
+                self.compile_block(&consequence.statements)?;
+                let jump = self.emit(Opcode::Jump, &[MAX_U16]);
+                self.patch_jump(jump_not_truthy, span)?;
+                match alternative {
+                    Some(alternative) => self.compile_block(&alternative.statements)?,
+                    None => {
+                        self.emit(Opcode::Null, &[]);
+                    }
+                }
+                self.patch_jump(jump, span)?;
+            }
+            ExpressionKind::FunctionLiteral { parameters, body } => {
+                self.compile_function(parameters, body, span)?
+            }
+            ExpressionKind::Call {
+                function,
+                arguments,
+            } => {
+                Self::check_count("arguments", arguments.len(), MAX_U8, span)?;
+                self.compile_expression(function)?;
+                for argument in arguments {
+                    self.compile_expression(argument)?;
+                }
+                self.emit_at(span, Opcode::Call, &[arguments.len()]);
+            }
+            ExpressionKind::ArrayLiteral(elements) => {
+                Self::check_count("array elements", elements.len(), MAX_U16, span)?;
+                for element in elements {
+                    self.compile_expression(element)?;
+                }
+                self.emit(Opcode::Array, &[elements.len()]);
+            }
+            ExpressionKind::HashLiteral(pairs) => {
+                Self::check_count("hash pairs", pairs.len(), MAX_U16, span)?;
+                for (key, value) in pairs {
+                    self.compile_expression(key)?;
+                    self.emit_at(key.span, Opcode::HashKey, &[]);
+                    self.compile_expression(value)?;
+                }
+                self.emit(Opcode::Hash, &[pairs.len()]);
+            }
+            ExpressionKind::Index { left, index } => {
+                self.compile_expression(left)?;
+                self.compile_expression(index)?;
+                self.emit_at(span, Opcode::Index, &[]);
+            }
+        }
+        Ok(())
+    }
+
+    fn compile_identifier(&mut self, name: &str, span: Span) -> Result<(), CompileError> {
+        match symbol_table::resolve(&self.tables, name) {
+            Some(Symbol::Local(index)) => {
+                self.emit_at(span, Opcode::GetLocal, &[index]);
+            }
+            Some(Symbol::Outer { depth, index }) => {
+                Self::check_count("nested functions", depth, MAX_U8, span)?;
+                self.emit_at(span, Opcode::GetOuter, &[depth, index]);
+            }
+            // Names that aren't bound in any enclosing function are looked up when the program
+            // runs, so that functions can refer to globals defined after them, and to builtins.
+            None => {
+                let index = self.global(name, span)?;
+                self.emit_at(span, Opcode::GetGlobal, &[index]);
+            }
+        }
+        Ok(())
+    }
+
+    /// Resolves what `name` refers to in the enclosing functions, for a slot of the function
+    /// being compiled to fall back to until it is bound.
+    fn fallback(&mut self, name: &str, span: Span) -> Result<Fallback, CompileError> {
+        Ok(match symbol_table::resolve(&self.tables, name) {
+            Some(Symbol::Local(index)) => Fallback::Outer { depth: 1, index },
+            Some(Symbol::Outer { depth, index }) => Fallback::Outer {
+                depth: depth + 1,
+                index,
+            },
+            None => Fallback::Global(self.global(name, span)?),
+        })
+    }
+
+    /// Compiles a function literal into a constant and emits the closure creating it.
+    fn compile_function(
+        &mut self,
+        parameters: &[Identifier],
+        body: &BlockStatement,
+        span: Span,
+    ) -> Result<(), CompileError> {
+        // Every name the function binds gets its slot up front, so that the closures it creates
+        // can refer to bindings made after them, as they can in the evaluator.
+        let mut table = SymbolTable::default();
+        for parameter in parameters {
+            table.define_parameter(&parameter.name);
+        }
+        declare_lets(&mut table, &body.statements);
+        Self::check_count("local bindings", table.names.len(), MAX_U8 + 1, span)?;
+
+        self.scopes.push(Scope::default());
+        self.tables.push(table);
+        let compiled = self
+            .compile_block(&body.statements)
+            .map(|()| self.emit(Opcode::ReturnValue, &[]));
+        let scope = self
+            .scopes
+            .pop()
+            .expect("the function's scope was pushed above");
+        let table = self
+            .tables
+            .pop()
+            .expect("the function's table was pushed above");
+        compiled?;
+        let fallbacks = table.names[parameters.len()..]
+            .iter()
+            .map(|name| self.fallback(name, span))
+            .collect::<Result<_, _>>()?;
+
+        let names: Vec<&str> = parameters.iter().map(|p| p.name.as_str()).collect();
+        let function = CompiledFunction {
+            instructions: scope.instructions,
+            locals: table.names,
+            num_parameters: parameters.len(),
+            fallbacks,
+            spans: scope.spans,
+            lines: scope.lines,
+            source: format!("fn({}) {}", names.join(", "), body),
+        };
+        let index = self.add_constant(Object::CompiledFunction(Rc::new(function)), span)?;
+        self.emit(Opcode::Closure, &[index]);
+        Ok(())
+    }
+}
+
+/// Defines the names bound by the `let` statements in `statements` in `table`, including those
+/// in nested blocks, which share the function's slots, but not those in nested functions.
+fn declare_lets(table: &mut SymbolTable, statements: &[Statement]) {
+    for statement in statements {
+        match &statement.kind {
+            StatementKind::Let(LetStatement {
+                variable,
+                expression,
+            }) => {
+                table.define(&variable.name);
+                declare_lets_in(table, expression);
+            }
+            StatementKind::Return(expression) | StatementKind::Expression(expression) => {
+                declare_lets_in(table, expression)
+            }
+        }
+    }
+}
+
+fn declare_lets_in(table: &mut SymbolTable, expression: &Expression) {
+    match &expression.kind {
+        ExpressionKind::IntegerLiteral(_)
+        | ExpressionKind::BooleanLiteral(_)
+        | ExpressionKind::StringLiteral(_)
+        | ExpressionKind::IdentifierLiteral(_)
+        | ExpressionKind::FunctionLiteral { .. } => {}
+        ExpressionKind::InterpolatedString(parts) => {
+            for part in parts {
+                if let InterpolationPart::Expression(expression) = part {
+                    declare_lets_in(table, expression);
+                }
+            }
+        }
+        ExpressionKind::Prefix { right, .. } => declare_lets_in(table, right),
+        ExpressionKind::Infix { left, right, .. }
+        | ExpressionKind::Index { left, index: right } => {
+            declare_lets_in(table, left);
+            declare_lets_in(table, right);
+        }
+        ExpressionKind::If {
+            condition,
+            consequence,
+            alternative,
+        } => {
+            declare_lets_in(table, condition);
+            declare_lets(table, &consequence.statements);
+            if let Some(alternative) = alternative {
+                declare_lets(table, &alternative.statements);
+            }
+        }
+        ExpressionKind::Call {
+            function,
+            arguments,
+        } => {
+            declare_lets_in(table, function);
+            for argument in arguments {
+                declare_lets_in(table, argument);
+            }
+        }
+        ExpressionKind::ArrayLiteral(elements) => {
+            for element in elements {
+                declare_lets_in(table, element);
+            }
+        }
+        ExpressionKind::HashLiteral(pairs) => {
+            for (key, value) in pairs {
+                declare_lets_in(table, key);
+                declare_lets_in(table, value);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ast::Parser;
+    use crate::code::{make, Disassembly};
+
+    fn compile_source(input: &str) -> Bytecode {
+        compile(&Parser::new(input).parse().unwrap()).unwrap()
+    }
+
+    fn disassemble(instructions: &[u8]) -> String {
+        Disassembly(instructions).to_string()
+    }
+
+    #[test]
+    fn compiles_expressions_and_globals() {
+        let bytecode = compile_source("let x = 1 + 2; -x");
+        assert_eq!(bytecode.constants, [Object::Integer(1), Object::Integer(2)]);
+        assert_eq!(bytecode.globals, ["x"]);
+        let expected = [
+            make(Opcode::Constant, &[0]),
+            make(Opcode::Constant, &[1]),
+            make(Opcode::Add, &[]),
+            make(Opcode::SetGlobal, &[0]),
+            make(Opcode::GetGlobal, &[0]),
+            make(Opcode::Minus, &[]),
+            make(Opcode::ReturnValue, &[]),
+        ]
+        .concat();
+        assert_eq!(
+            disassemble(&bytecode.main.instructions),
+            disassemble(&expected)
+        );
+        // Only the instructions that can fail have a span.
+        let offsets: Vec<usize> = bytecode.main.spans.iter().map(|&(o, _)| o).collect();
+        assert_eq!(offsets, [6, 10, 13]);
+    }
+
+    #[test]
+    fn compiles_conditionals() {
+        let bytecode = compile_source("if (true) { 10 }; 3333;");
+        let expected = [
+            make(Opcode::True, &[]),
+            make(Opcode::JumpNotTruthy, &[10]),
+            make(Opcode::Constant, &[0]),
+            make(Opcode::Jump, &[11]),
+            make(Opcode::Null, &[]),
+            make(Opcode::Pop, &[]),
+            make(Opcode::Constant, &[1]),
+            make(Opcode::ReturnValue, &[]),
+        ]
+        .concat();
+        assert_eq!(
+            disassemble(&bytecode.main.instructions),
+            disassemble(&expected)
+        );
+    }
+
+    #[test]
+    fn compiles_closures() {
+        let bytecode = compile_source("fn(a) { fn(b) { a + b } }");
+        let Object::CompiledFunction(inner) = &bytecode.constants[0] else {
+            panic!("expected a function, got {:?}", bytecode.constants[0]);
+        };
+        let expected = [
+            make(Opcode::GetOuter, &[1, 0]),
+            make(Opcode::GetLocal, &[0]),
+            make(Opcode::Add, &[]),
+            make(Opcode::ReturnValue, &[]),
+        ]
+        .concat();
+        assert_eq!(disassemble(&inner.instructions), disassemble(&expected));
+        assert_eq!(inner.source, "fn(b) { (a + b) }");
+
+        let Object::CompiledFunction(outer) = &bytecode.constants[1] else {
+            panic!("expected a function, got {:?}", bytecode.constants[1]);
+        };
+        let expected = [make(Opcode::Closure, &[0]), make(Opcode::ReturnValue, &[])].concat();
+        assert_eq!(disassemble(&outer.instructions), disassemble(&expected));
+        assert_eq!(
+            (outer.locals.as_slice(), outer.num_parameters),
+            (&["a".to_string()][..], 1)
+        );
+    }
+
+    #[test]
+    fn gives_each_name_of_a_function_one_slot() {
+        let bytecode = compile_source(
+            "fn(x) { let f = fn() { y }; if (x) { let x = 2; let z = 3; } let y = f; \
+             fn() { let w = 1; } }",
+        );
+        let Some(Object::CompiledFunction(function)) = bytecode.constants.last() else {
+            panic!("expected a function, got {:?}", bytecode.constants.last());
+        };
+        assert_eq!(function.locals, ["x", "f", "z", "y"]);
+        let Object::CompiledFunction(closure) = &bytecode.constants[0] else {
+            panic!("expected a function, got {:?}", bytecode.constants[0]);
+        };
+        let expected = [
+            make(Opcode::GetOuter, &[1, 3]),
+            make(Opcode::ReturnValue, &[]),
+        ]
+        .concat();
+        assert_eq!(disassemble(&closure.instructions), disassemble(&expected));
+    }
+
+    #[test]
+    fn resolves_what_unbound_slots_fall_back_to() {
+        let bytecode =
+            compile_source("fn(x) { let y = 1; fn() { let x = 2; let y = 3; let z = 4; } }");
+        let Object::CompiledFunction(inner) = &bytecode.constants[4] else {
+            panic!("expected a function, got {:?}", bytecode.constants[4]);
+        };
+        assert_eq!(
+            inner.fallbacks,
+            [
+                Fallback::Outer { depth: 1, index: 0 },
+                Fallback::Outer { depth: 1, index: 1 },
+                Fallback::Global(0),
+            ]
+        );
+        let Some(Object::CompiledFunction(outer)) = bytecode.constants.last() else {
+            panic!("expected a function, got {:?}", bytecode.constants.last());
+        };
+        assert_eq!(outer.fallbacks, [Fallback::Global(1)]);
+        assert_eq!(bytecode.globals, ["z", "y"]);
+    }
+
+    #[test]
+    fn reports_limits() {
+        let arguments = vec!["1"; 256].join(", ");
+        let program = Parser::new(&format!("f({})", arguments)).parse().unwrap();
+        assert_eq!(
+            compile(&program).unwrap_err().to_string(),
+            "too many arguments, the limit is 255"
+        );
+    }
+}
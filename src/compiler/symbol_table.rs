@@ -0,0 +1,75 @@
+use std::collections::HashMap;
+
+/// Where the value a name refers to lives at runtime.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub(super) enum Symbol {
+    /// A slot in the frame of the function being compiled.
+    Local(usize),
+    /// A slot in the frame of an enclosing function, `depth` functions out.
+    Outer { depth: usize, index: usize },
+}
+
+/// The names defined in one function, each with a slot in the frame of its calls. Names that
+/// aren't found in any enclosing function are globals, which the compiler tracks separately.
+#[derive(Debug, Default)]
+pub(super) struct SymbolTable {
+    store: HashMap<String, usize>,
+    /// The name of each slot.
+    pub(super) names: Vec<String>,
+}
+
+impl SymbolTable {
+    /// Defines a parameter in a new slot, shadowing any earlier parameter of the same name.
+    pub(super) fn define_parameter(&mut self, name: &str) -> usize {
+        self.names.push(name.to_string());
+        self.store.insert(name.to_string(), self.names.len() - 1);
+        self.names.len() - 1
+    }
+
+    /// Returns the slot of `name`, allocating one if it isn't defined yet. Like the evaluator's
+    /// scopes, a function has a single slot per name, shared by all of its blocks, that each
+    /// `let` of the name overwrites.
+    pub(super) fn define(&mut self, name: &str) -> usize {
+        match self.store.get(name) {
+            Some(&index) => index,
+            None => self.define_parameter(name),
+        }
+    }
+}
+
+/// Resolves `name` in `tables`, the functions being compiled, innermost last. Returns `None` for
+/// globals.
+pub(super) fn resolve(tables: &[SymbolTable], name: &str) -> Option<Symbol> {
+    tables.iter().rev().enumerate().find_map(|(depth, table)| {
+        let index = *table.store.get(name)?;
+        Some(match depth {
+            0 => Symbol::Local(index),
+            depth => Symbol::Outer { depth, index },
+        })
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn resolves_locals_outer_slots_and_globals() {
+        let mut tables = vec![SymbolTable::default(), SymbolTable::default()];
+        assert_eq!(tables[0].define("a"), 0);
+        assert_eq!(tables[0].define("b"), 1);
+        assert_eq!(tables[0].define("a"), 0);
+        assert_eq!(tables[1].define_parameter("c"), 0);
+        assert_eq!(tables[1].define_parameter("c"), 1);
+        assert_eq!(tables[1].define("c"), 1);
+
+        assert_eq!(resolve(&tables, "c"), Some(Symbol::Local(1)));
+        assert_eq!(
+            resolve(&tables, "b"),
+            Some(Symbol::Outer { depth: 1, index: 1 })
+        );
+        assert_eq!(resolve(&tables, "g"), None);
+        assert_eq!(tables[0].names, ["a", "b"]);
+        assert_eq!(tables[1].names, ["c", "c"]);
+    }
+}
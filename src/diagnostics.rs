@@ -0,0 +1,289 @@
+use crate::ast::{Expected, ParseError};
+use crate::compiler::{CompileError, CompileErrorKind};
+use crate::eval::{EvalError, EvalErrorKind};
+use crate::token::{Span, Token};
+use std::fmt::Write;
+
+/// Whether rendered diagnostics contain ANSI escape codes.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Mode {
+    Plain,
+    Color,
+}
+
+impl Mode {
+    /// Picks `Color` if stderr is a terminal, `Plain` otherwise.
+    pub fn for_stderr() -> Self {
+        use std::io::IsTerminal;
+        if std::io::stderr().is_terminal() {
+            Mode::Color
+        } else {
+            Mode::Plain
+        }
+    }
+
+    fn paint(self, style: &str, text: &str) -> String {
+        match self {
+            Mode::Plain => text.to_string(),
+            Mode::Color => format!("\x1b[{}m{}\x1b[0m", style, text),
+        }
+    }
+}
+
+const BOLD_RED: &str = "1;31";
+const BOLD_BLUE: &str = "1;34";
+const BOLD: &str = "1";
+
+/// An error message tied to a location in the source.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct Diagnostic {
+    pub code: &'static str,
+    pub message: String,
+    pub span: Span,
+    pub help: Vec<String>,
+}
+
+impl Diagnostic {
+    pub fn new(code: &'static str, message: impl Into<String>, span: Span) -> Self {
+        Self {
+            code,
+            message: message.into(),
+            span,
+            help: Vec::new(),
+        }
+    }
+
+    pub fn with_help(mut self, help: impl Into<String>) -> Self {
+        self.help.push(help.into());
+        self
+    }
+
+    /// Renders the diagnostic along with the offending source line and a caret underline.
+    ///
+    /// `origin` names the source in the location line, e.g. a file path or `<repl>`.
+    ///
+    /// ```text
+    /// error[E0101]: type mismatch: INTEGER + BOOLEAN
+    ///  --> script.monkey:2:3
+    ///   |
+    /// 2 |   x + true
+    ///   |   ^^^^^^^^
+    /// ```
+    pub fn render(&self, source: &str, origin: &str, mode: Mode) -> String {
+        let bytes = source.as_bytes();
+        let start = self.span.start.min(bytes.len());
+        let line_start = bytes[..start]
+            .iter()
+            .rposition(|&b| b == b'\n')
+            .map_or(0, |i| i + 1);
+        let line_end = bytes[start..]
+            .iter()
+            .position(|&b| b == b'\n')
+            .map_or(bytes.len(), |i| start + i);
+        let end = self.span.end.clamp(start, line_end);
+
+        let text = |range: std::ops::Range<usize>| String::from_utf8_lossy(&bytes[range]);
+        let line = text(line_start..line_end);
+        let indent = text(line_start..start).chars().count();
+        let width = text(start..end).chars().count().max(1);
+
+        let number = self.span.line.to_string();
+        let gutter = " ".repeat(number.len());
+        let bar = mode.paint(BOLD_BLUE, "|");
+
+        let mut out = String::new();
+        let _ = writeln!(
+            out,
+            "{}{}",
+            mode.paint(BOLD_RED, &format!("error[{}]", self.code)),
+            mode.paint(BOLD, &format!(": {}", self.message))
+        );
+        let _ = writeln!(
+            out,
+            "{}{} {}:{}:{}",
+            gutter,
+            mode.paint(BOLD_BLUE, "-->"),
+            origin,
+            self.span.line,
+            self.span.column
+        );
+        let _ = writeln!(out, "{} {}", gutter, bar);
+        let _ = writeln!(out, "{} {} {}", mode.paint(BOLD_BLUE, &number), bar, line);
+        let _ = writeln!(
+            out,
+            "{} {} {}{}",
+            gutter,
+            bar,
+            " ".repeat(indent),
+            mode.paint(BOLD_RED, &"^".repeat(width))
+        );
+        for help in &self.help {
+            let _ = writeln!(
+                out,
+                "{} {} {}: {}",
+                gutter,
+                mode.paint(BOLD_BLUE, "="),
+                mode.paint(BOLD, "help"),
+                help
+            );
+        }
+        out
+    }
+}
+
+impl From<&ParseError> for Diagnostic {
+    fn from(error: &ParseError) -> Self {
+        let code = match (&error.expected, &error.found) {
+            (_, Token::UnterminatedString) => "E0006",
+            (_, Token::InvalidEscape(_)) => "E0007",
+            (Expected::Token(_), _) => "E0001",
+            (Expected::Identifier, _) => "E0002",
+            (Expected::Expression, _) => "E0003",
+            (Expected::Operator, _) => "E0004",
+            (Expected::Integer, _) => "E0005",
+            (Expected::LessNesting, _) => "E0008",
+        };
+        let diagnostic = Diagnostic::new(code, error.to_string(), error.span);
+        match (&error.expected, &error.found) {
+            (Expected::LessNesting, _) => {
+                diagnostic.with_help("bind the inner expressions with `let` statements")
+            }
+            (_, Token::Eof) => {
+                diagnostic.with_help("the input ended before the statement was complete")
+            }
+            (Expected::Integer, _) => diagnostic.with_help(format!(
+                "integer literals must be between {} and {}",
+                i64::MIN,
+                i64::MAX
+            )),
+            (_, Token::UnterminatedString) => {
+                diagnostic.with_help("add a `\"` to close the string")
+            }
+            (_, Token::InvalidEscape(_)) => diagnostic.with_help(
+                r#"the supported escapes are \n, \t, \", \\, \$ and \u{...} with 1 to 6 hex digits"#,
+            ),
+            (_, Token::Illegal(_)) => {
+                diagnostic.with_help("this character is not part of the Monkey language")
+            }
+            _ => diagnostic,
+        }
+    }
+}
+
+impl From<&EvalError> for Diagnostic {
+    fn from(error: &EvalError) -> Self {
+        let code = match error.kind {
+            EvalErrorKind::IdentifierNotFound(_) => "E0100",
+            EvalErrorKind::TypeMismatch { .. } => "E0101",
+            EvalErrorKind::UnknownPrefixOperator { .. } => "E0102",
+            EvalErrorKind::UnknownInfixOperator { .. } => "E0103",
+            EvalErrorKind::DivisionByZero => "E0104",
+            EvalErrorKind::NotAFunction(_) => "E0105",
+            EvalErrorKind::WrongArgumentCount { .. } => "E0106",
+            EvalErrorKind::UnsupportedArgument { .. } => "E0107",
+            EvalErrorKind::IndexNotSupported { .. } => "E0108",
+            EvalErrorKind::UnusableAsHashKey(_) => "E0109",
+            EvalErrorKind::TooFewArguments { .. } => "E0110",
+            EvalErrorKind::Custom(_) => "E0111",
+            EvalErrorKind::StackOverflow => "E0112",
+        };
+        let diagnostic = Diagnostic::new(code, error.to_string(), error.span);
+        match &error.kind {
+            EvalErrorKind::IdentifierNotFound(name) => {
+                diagnostic.with_help(format!("bind it before use, e.g. `let {} = ...;`", name))
+            }
+            EvalErrorKind::TypeMismatch { .. } => {
+                diagnostic.with_help("both operands of an infix operator must have the same type")
+            }
+            EvalErrorKind::IndexNotSupported { .. } => {
+                diagnostic.with_help("arrays are indexed with integers, and hashes with their keys")
+            }
+            EvalErrorKind::UnusableAsHashKey(_) => {
+                diagnostic.with_help("only integers, booleans and strings can be hash keys")
+            }
+            _ => diagnostic,
+        }
+    }
+}
+
+impl From<&CompileError> for Diagnostic {
+    fn from(error: &CompileError) -> Self {
+        let diagnostic = Diagnostic::new("E0200", error.to_string(), error.span);
+        match error.kind {
+            CompileErrorKind::TooMany { .. } => {
+                diagnostic.with_help("the evaluator has no such limit, try `--engine eval`")
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ast::Parser;
+    use crate::eval::{self, Environment};
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    #[test]
+    fn renders_parse_errors() {
+        let source = "let a = 1;\nlet b = a +;";
+        let errors = Parser::new(source).parse().unwrap_err();
+        let rendered = Diagnostic::from(&errors[0]).render(source, "test.monkey", Mode::Plain);
+        assert_eq!(
+            rendered,
+            "error[E0003]: expected an expression, found `;`
+ --> test.monkey:2:12
+  |
+2 | let b = a +;
+  |            ^
+"
+        );
+    }
+
+    #[test]
+    fn renders_runtime_errors_with_help() {
+        let source = "let f = fn(x) {\n  x + true\n};\nf(1)";
+        let program = Parser::new(source).parse().unwrap();
+        let env = Rc::new(RefCell::new(Environment::new()));
+        let error = eval::eval(&program, &env).unwrap_err();
+        let rendered = Diagnostic::from(&error).render(source, "<repl>", Mode::Plain);
+        assert_eq!(
+            rendered,
+            "error[E0101]: type mismatch: INTEGER + BOOLEAN
+ --> <repl>:2:3
+  |
+2 |   x + true
+  |   ^^^^^^^^
+  = help: both operands of an infix operator must have the same type
+"
+        );
+    }
+
+    #[test]
+    fn color_mode_wraps_parts_in_ansi_codes() {
+        let source = "add(1";
+        let errors = Parser::new(source).parse().unwrap_err();
+        let rendered = Diagnostic::from(&errors[0]).render(source, "<repl>", Mode::Color);
+        assert!(rendered.starts_with("\x1b[1;31merror[E0001]\x1b[0m"));
+        assert!(rendered.contains("\x1b[1;31m^\x1b[0m"));
+        assert!(rendered.contains("the input ended before the statement was complete"));
+    }
+
+    #[test]
+    fn unterminated_strings_point_at_the_opening_quote() {
+        let source = "let s = \"abc;\nlet t = 1;";
+        let errors = Parser::new(source).parse().unwrap_err();
+        let rendered = Diagnostic::from(&errors[0]).render(source, "<repl>", Mode::Plain);
+        assert_eq!(
+            rendered,
+            "error[E0006]: unterminated string
+ --> <repl>:1:9
+  |
+1 | let s = \"abc;
+  |         ^
+  = help: add a `\"` to close the string
+"
+        );
+    }
+}
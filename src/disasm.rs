@@ -0,0 +1,136 @@
+use crate::code::{self, CompiledFunction, Opcode};
+use crate::compiler::Bytecode;
+use crate::eval::Object;
+use std::fmt;
+
+/// Wraps a compiled program to print it for debugging code generation. Each instruction is shown
+/// with its offset, operands and what they refer to, under the source line it was compiled from.
+/// Functions are listed, indented, after the function that creates them.
+pub struct Listing<'a> {
+    pub bytecode: &'a Bytecode,
+    /// The source the program was compiled from.
+    pub source: &'a str,
+}
+
+impl Listing<'_> {
+    /// Lists `function`, which is nested in the functions of `enclosing`, innermost last.
+    fn function<'b>(
+        &'b self,
+        f: &mut fmt::Formatter<'_>,
+        function: &'b CompiledFunction,
+        enclosing: &mut Vec<&'b CompiledFunction>,
+    ) -> fmt::Result {
+        let indent = "  ".repeat(enclosing.len());
+        let mut nested = Vec::new();
+        let mut line = None;
+        let mut offset = 0;
+        while offset < function.instructions.len() {
+            if let Some(current) = function.line_at(offset).filter(|&l| line != Some(l)) {
+                let text = self.source.lines().nth(current - 1).unwrap_or("");
+                writeln!(f, "{}{:>4} | {}", indent, current, text.trim_end())?;
+                line = Some(current);
+            }
+            let byte = function.instructions[offset];
+            let Ok(opcode) = Opcode::try_from(byte) else {
+                writeln!(f, "{}{:04} <unknown opcode {}>", indent, offset, byte)?;
+                offset += 1;
+                continue;
+            };
+            let (operands, read) =
+                code::read_operands(opcode, &function.instructions[offset + 1..]);
+            let mut instruction = opcode.name().to_string();
+            for operand in &operands {
+                instruction += &format!(" {}", operand);
+            }
+            match self.describe(opcode, &operands, function, enclosing) {
+                Some(note) => {
+                    writeln!(f, "{}{:04} {:<22} ; {}", indent, offset, instruction, note)?
+                }
+                None => writeln!(f, "{}{:04} {}", indent, offset, instruction)?,
+            }
+            if opcode == Opcode::Closure {
+                if let Some(Object::CompiledFunction(function)) =
+                    self.bytecode.constants.get(operands[0])
+                {
+                    nested.push((operands[0], function));
+                }
+            }
+            offset += 1 + read;
+        }
+        enclosing.push(function);
+        for (index, nested) in nested {
+            writeln!(f)?;
+            writeln!(f, "{}  constant {}: {}", indent, index, nested)?;
+            self.function(f, nested, enclosing)?;
+        }
+        enclosing.pop();
+        Ok(())
+    }
+
+    /// Returns what the operands of an instruction refer to, if they are indices.
+    fn describe(
+        &self,
+        opcode: Opcode,
+        operands: &[usize],
+        function: &CompiledFunction,
+        enclosing: &[&CompiledFunction],
+    ) -> Option<String> {
+        match opcode {
+            Opcode::Constant => Some(self.bytecode.constants.get(operands[0])?.inspect()),
+            Opcode::Closure => Some(self.bytecode.constants.get(operands[0])?.to_string()),
+            Opcode::GetGlobal | Opcode::SetGlobal => {
+                self.bytecode.globals.get(operands[0]).cloned()
+            }
+            Opcode::GetLocal | Opcode::SetLocal => function.locals.get(operands[0]).cloned(),
+            Opcode::GetOuter => {
+                let outer = enclosing.get(enclosing.len().checked_sub(operands[0])?)?;
+                outer.locals.get(operands[1]).cloned()
+            }
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for Listing<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "main:")?;
+        self.function(f, &self.bytecode.main, &mut Vec::new())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ast::Parser;
+    use crate::compiler;
+
+    #[test]
+    fn lists_functions_with_their_source_lines() {
+        let source = "let greet = fn(name) {\n  \"hi \" + name\n};\ngreet(\"bob\")";
+        let bytecode = compiler::compile(&Parser::new(source).parse().unwrap()).unwrap();
+        let listing = Listing {
+            bytecode: &bytecode,
+            source,
+        };
+        assert_eq!(
+            listing.to_string(),
+            r#"main:
+   1 | let greet = fn(name) {
+0000 OpClosure 1            ; fn(name) { ("hi " + name) }
+0003 OpSetGlobal 0          ; greet
+   4 | greet("bob")
+0006 OpGetGlobal 0          ; greet
+0009 OpConstant 2           ; "bob"
+0012 OpCall 1
+0014 OpReturnValue
+
+  constant 1: fn(name) { ("hi " + name) }
+     2 |   "hi " + name
+  0000 OpConstant 0           ; "hi "
+  0003 OpGetLocal 0           ; name
+  0005 OpAdd
+  0006 OpReturnValue
+"#
+        );
+    }
+}
@@ -0,0 +1,1096 @@
+use crate::ast::{
+    BlockStatement, Expression, ExpressionKind, Identifier, InfixOperator, InterpolationPart,
+    LetStatement, PrefixOperator, Program, Statement, StatementKind,
+};
+use crate::builtins::{Builtin, Builtins};
+use crate::code::CompiledFunction;
+use crate::token::{self, Span};
+use crate::vm::Closure;
+use std::cell::{Cell, RefCell};
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::rc::Rc;
+
+/// A runtime value produced by evaluating the AST.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum Object {
+    Integer(i64),
+    Boolean(bool),
+    String(String),
+    Null,
+    Function(Rc<Function>),
+    Builtin(Rc<Builtin>),
+    Array(Vec<Object>),
+    /// Kept ordered by key so that hashes display the same way every time.
+    Hash(BTreeMap<HashKey, Object>),
+    /// A function compiled for the `vm`, as stored in the constant pool.
+    CompiledFunction(Rc<CompiledFunction>),
+    Closure(Rc<Closure>),
+}
+
+impl Object {
+    /// Returns the name of the value's type, as used in error messages.
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            Object::Integer(_) => "INTEGER",
+            Object::Boolean(_) => "BOOLEAN",
+            Object::String(_) => "STRING",
+            Object::Null => "NULL",
+            Object::Function(_) => "FUNCTION",
+            Object::Builtin(_) => "BUILTIN",
+            Object::Array(_) => "ARRAY",
+            Object::Hash(_) => "HASH",
+            Object::CompiledFunction(_) | Object::Closure(_) => "FUNCTION",
+        }
+    }
+
+    /// Formats the value the way it would be written in source, quoting strings, as used when it
+    /// is nested in another value.
+    pub(crate) fn inspect(&self) -> String {
+        match self {
+            Object::String(value) => format!("\"{}\"", token::escape(value)),
+            other => other.to_string(),
+        }
+    }
+}
+
+impl fmt::Display for Object {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Object::Integer(value) => write!(f, "{}", value),
+            Object::Boolean(value) => write!(f, "{}", value),
+            Object::String(value) => write!(f, "{}", value),
+            Object::Null => write!(f, "null"),
+            Object::Function(function) => write!(f, "{}", function),
+            Object::Builtin(builtin) => write!(f, "{}", builtin),
+            Object::Array(elements) => {
+                let elements: Vec<String> = elements.iter().map(|e| e.inspect()).collect();
+                write!(f, "[{}]", elements.join(", "))
+            }
+            Object::Hash(pairs) => {
+                let pairs: Vec<String> = pairs
+                    .iter()
+                    .map(|(key, value)| format!("{}: {}", key, value.inspect()))
+                    .collect();
+                write!(f, "{{{}}}", pairs.join(", "))
+            }
+            Object::CompiledFunction(function) => write!(f, "{}", function),
+            Object::Closure(closure) => write!(f, "{}", closure.function),
+        }
+    }
+}
+
+/// The value of a hash key, which is what entries are looked up by.
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Clone)]
+pub enum HashKey {
+    Integer(i64),
+    Boolean(bool),
+    String(String),
+}
+
+impl fmt::Display for HashKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            HashKey::Integer(value) => write!(f, "{}", value),
+            HashKey::Boolean(value) => write!(f, "{}", value),
+            HashKey::String(value) => write!(f, "\"{}\"", token::escape(value)),
+        }
+    }
+}
+
+/// Implemented by values that can be used as keys in a hash.
+pub trait Hashable {
+    fn hash_key(&self) -> Result<HashKey, EvalErrorKind>;
+}
+
+impl Hashable for Object {
+    fn hash_key(&self) -> Result<HashKey, EvalErrorKind> {
+        match self {
+            Object::Integer(value) => Ok(HashKey::Integer(*value)),
+            Object::Boolean(value) => Ok(HashKey::Boolean(*value)),
+            Object::String(value) => Ok(HashKey::String(value.clone())),
+            other => Err(EvalErrorKind::UnusableAsHashKey(other.type_name())),
+        }
+    }
+}
+
+/// A user-defined function together with the environment it was defined in.
+pub struct Function {
+    pub parameters: Vec<Identifier>,
+    pub body: BlockStatement,
+    pub env: Env,
+}
+
+impl fmt::Display for Function {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let parameters: Vec<&str> = self.parameters.iter().map(|p| p.name.as_str()).collect();
+        write!(f, "fn({}) {}", parameters.join(", "), self.body)
+    }
+}
+
+// The captured environment is left out, as it usually contains the function itself.
+impl fmt::Debug for Function {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Function")
+            .field("parameters", &self.parameters)
+            .field("body", &self.body)
+            .finish_non_exhaustive()
+    }
+}
+
+/// Functions are compared by identity.
+impl PartialEq for Function {
+    fn eq(&self, other: &Self) -> bool {
+        std::ptr::eq(self, other)
+    }
+}
+
+impl Eq for Function {}
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum EvalErrorKind {
+    IdentifierNotFound(String),
+    TypeMismatch {
+        left: &'static str,
+        operator: InfixOperator,
+        right: &'static str,
+    },
+    UnknownPrefixOperator {
+        operator: PrefixOperator,
+        right: &'static str,
+    },
+    UnknownInfixOperator {
+        left: &'static str,
+        operator: InfixOperator,
+        right: &'static str,
+    },
+    DivisionByZero,
+    NotAFunction(&'static str),
+    WrongArgumentCount {
+        expected: usize,
+        found: usize,
+    },
+    UnsupportedArgument {
+        function: &'static str,
+        found: &'static str,
+    },
+    IndexNotSupported {
+        left: &'static str,
+        index: &'static str,
+    },
+    UnusableAsHashKey(&'static str),
+    TooFewArguments {
+        minimum: usize,
+        found: usize,
+    },
+    StackOverflow,
+    /// An error raised by a builtin registered by the embedding application.
+    Custom(String),
+}
+
+impl fmt::Display for EvalErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            EvalErrorKind::IdentifierNotFound(name) => write!(f, "identifier not found: {}", name),
+            EvalErrorKind::TypeMismatch {
+                left,
+                operator,
+                right,
+            } => write!(f, "type mismatch: {} {} {}", left, operator, right),
+            EvalErrorKind::UnknownPrefixOperator { operator, right } => {
+                write!(f, "unknown operator: {}{}", operator, right)
+            }
+            EvalErrorKind::UnknownInfixOperator {
+                left,
+                operator,
+                right,
+            } => write!(f, "unknown operator: {} {} {}", left, operator, right),
+            EvalErrorKind::DivisionByZero => write!(f, "division by zero"),
+            EvalErrorKind::NotAFunction(type_name) => write!(f, "not a function: {}", type_name),
+            EvalErrorKind::WrongArgumentCount { expected, found } => write!(
+                f,
+                "wrong number of arguments: expected {}, found {}",
+                expected, found
+            ),
+            EvalErrorKind::UnsupportedArgument { function, found } => {
+                write!(f, "argument to `{}` not supported, got {}", function, found)
+            }
+            EvalErrorKind::IndexNotSupported { left, index } => {
+                write!(f, "index operator not supported: {}[{}]", left, index)
+            }
+            EvalErrorKind::UnusableAsHashKey(type_name) => {
+                write!(f, "unusable as hash key: {}", type_name)
+            }
+            EvalErrorKind::TooFewArguments { minimum, found } => write!(
+                f,
+                "wrong number of arguments: expected at least {}, found {}",
+                minimum, found
+            ),
+            EvalErrorKind::StackOverflow => write!(f, "stack overflow"),
+            EvalErrorKind::Custom(message) => write!(f, "{}", message),
+        }
+    }
+}
+
+/// A runtime error, located at the expression whose evaluation failed.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct EvalError {
+    pub kind: EvalErrorKind,
+    pub span: Span,
+}
+
+impl fmt::Display for EvalError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.kind)
+    }
+}
+
+impl std::error::Error for EvalError {}
+
+/// An `Environment` shared between the evaluator and the closures created in it.
+pub type Env = Rc<RefCell<Environment>>;
+
+/// Holds the values bound by `let` statements, falling back to the enclosing scope and then to the
+/// builtins on lookup.
+#[derive(Debug)]
+pub struct Environment {
+    store: HashMap<String, Object>,
+    outer: Option<Env>,
+    builtins: Rc<Builtins>,
+    stack_limit: usize,
+}
+
+impl Environment {
+    /// Creates an empty environment with the standard builtins.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Creates an empty environment that falls back to `builtins` instead of the standard ones.
+    pub fn with_builtins(builtins: Builtins) -> Self {
+        Self {
+            store: HashMap::new(),
+            outer: None,
+            builtins: Rc::new(builtins),
+            stack_limit: DEFAULT_STACK_LIMIT,
+        }
+    }
+
+    /// Creates a scope nested in `outer`, as used for function calls.
+    pub fn new_enclosed(outer: Env) -> Self {
+        let (builtins, stack_limit) = {
+            let outer = outer.borrow();
+            (Rc::clone(&outer.builtins), outer.stack_limit)
+        };
+        Self {
+            store: HashMap::new(),
+            outer: Some(outer),
+            builtins,
+            stack_limit,
+        }
+    }
+
+    /// Sets how many bytes of native stack evaluating code in this environment may use before
+    /// it fails with a stack overflow, `DEFAULT_STACK_LIMIT` unless set. Scopes created from
+    /// this one afterwards share the limit.
+    pub fn set_stack_limit(&mut self, bytes: usize) {
+        self.stack_limit = bytes;
+    }
+
+    pub fn get(&self, name: &str) -> Option<Object> {
+        self.lookup(name).or_else(|| self.builtins.get(name))
+    }
+
+    /// Looks `name` up in this scope and the enclosing ones, ignoring the builtins.
+    fn lookup(&self, name: &str) -> Option<Object> {
+        match self.store.get(name) {
+            Some(value) => Some(value.clone()),
+            None => self.outer.as_ref()?.borrow().lookup(name),
+        }
+    }
+
+    pub fn set(&mut self, name: &str, value: Object) {
+        self.store.insert(name.to_string(), value);
+    }
+
+    /// Returns the bindings of this scope, sorted by name. Enclosing scopes are not included.
+    pub fn bindings(&self) -> Vec<(&str, &Object)> {
+        let mut bindings: Vec<(&str, &Object)> = self
+            .store
+            .iter()
+            .map(|(name, value)| (name.as_str(), value))
+            .collect();
+        bindings.sort_by_key(|&(name, _)| name);
+        bindings
+    }
+
+    /// Removes all bindings of this scope, keeping the builtins.
+    pub fn clear(&mut self) {
+        self.store.clear();
+    }
+
+    pub fn builtins(&self) -> &Builtins {
+        &self.builtins
+    }
+}
+
+impl Default for Environment {
+    fn default() -> Self {
+        Self::with_builtins(Builtins::standard())
+    }
+}
+
+/// Evaluates the program's statements in order and returns the value of the last one, or of
+/// the first `return` statement reached.
+pub fn eval(program: &Program, env: &Env) -> Result<Object, EvalError> {
+    let _stack = StackGuard::enter(env.borrow().stack_limit);
+    match eval_statements(&program.statements, env) {
+        Ok(value) | Err(Unwind::Return(value)) => Ok(value),
+        Err(Unwind::Error(error)) => Err(error),
+    }
+}
+
+/// Why evaluation stopped before reaching the end of an expression: either a `return` statement,
+/// whose value is passed up to the enclosing function or program, or an error.
+enum Unwind {
+    Return(Object),
+    Error(EvalError),
+}
+
+impl From<EvalError> for Unwind {
+    fn from(error: EvalError) -> Self {
+        Unwind::Error(error)
+    }
+}
+
+fn eval_statements(statements: &[Statement], env: &Env) -> Result<Object, Unwind> {
+    let mut result = Object::Null;
+    for statement in statements {
+        result = eval_statement(statement, env)?;
+    }
+    Ok(result)
+}
+
+fn eval_statement(statement: &Statement, env: &Env) -> Result<Object, Unwind> {
+    match &statement.kind {
+        StatementKind::Let(LetStatement {
+            variable,
+            expression,
+        }) => {
+            let value = eval_expression(expression, env)?;
+            env.borrow_mut().set(&variable.name, value);
+            Ok(Object::Null)
+        }
+        StatementKind::Return(expression) => Err(Unwind::Return(eval_expression(expression, env)?)),
+        StatementKind::Expression(expression) => eval_expression(expression, env),
+    }
+}
+
+fn eval_expression(expression: &Expression, env: &Env) -> Result<Object, Unwind> {
+    let error = |kind| {
+        Unwind::Error(EvalError {
+            kind,
+            span: expression.span,
+        })
+    };
+    // Deeply nested expressions use the native stack even without any calls.
+    if stack_exhausted() {
+        return Err(error(EvalErrorKind::StackOverflow));
+    }
+    match &expression.kind {
+        ExpressionKind::IntegerLiteral(value) => Ok(Object::Integer(*value)),
+        ExpressionKind::BooleanLiteral(value) => Ok(Object::Boolean(*value)),
+        ExpressionKind::StringLiteral(value) => Ok(Object::String(value.clone())),
+        ExpressionKind::InterpolatedString(parts) => {
+            let mut value = String::new();
+            for part in parts {
+                match part {
+                    InterpolationPart::Literal(text) => value.push_str(text),
+                    InterpolationPart::Expression(expression) => {
+                        value.push_str(&eval_expression(expression, env)?.to_string())
+                    }
+                }
+            }
+            Ok(Object::String(value))
+        }
+        ExpressionKind::IdentifierLiteral(name) => env
+            .borrow()
+            .get(name)
+            .ok_or_else(|| error(EvalErrorKind::IdentifierNotFound(name.clone()))),
+        ExpressionKind::Prefix { operator, right } => {
+            let right = eval_expression(right, env)?;
+            eval_prefix_expression(*operator, right).map_err(error)
+        }
+        ExpressionKind::Infix {
+            left,
+            operator,
+            right,
+        } => {
+            let left = eval_expression(left, env)?;
+            let right = eval_expression(right, env)?;
+            eval_infix_expression(left, *operator, right).map_err(error)
+        }
+        ExpressionKind::If {
+            condition,
+            consequence,
+            alternative,
+        } => {
+            let condition = eval_expression(condition, env)?;
+            if is_truthy(&condition) {
+                eval_statements(&consequence.statements, env)
+            } else if let Some(alternative) = alternative {
+                eval_statements(&alternative.statements, env)
+            } else {
+                Ok(Object::Null)
+            }
+        }
+        ExpressionKind::FunctionLiteral { parameters, body } => {
+            Ok(Object::Function(Rc::new(Function {
+                parameters: parameters.clone(),
+                body: body.clone(),
+                env: Rc::clone(env),
+            })))
+        }
+        ExpressionKind::Call {
+            function,
+            arguments,
+        } => {
+            let function = eval_expression(function, env)?;
+            let arguments = arguments
+                .iter()
+                .map(|argument| eval_expression(argument, env))
+                .collect::<Result<Vec<_>, _>>()?;
+            Ok(apply_function(function, arguments, expression.span)?)
+        }
+        ExpressionKind::ArrayLiteral(elements) => Ok(Object::Array(
+            elements
+                .iter()
+                .map(|element| eval_expression(element, env))
+                .collect::<Result<_, _>>()?,
+        )),
+        ExpressionKind::HashLiteral(pairs) => {
+            let mut hash = BTreeMap::new();
+            for (key, value) in pairs {
+                let hash_key = eval_expression(key, env)?
+                    .hash_key()
+                    .map_err(|kind| EvalError {
+                        kind,
+                        span: key.span,
+                    })?;
+                hash.insert(hash_key, eval_expression(value, env)?);
+            }
+            Ok(Object::Hash(hash))
+        }
+        ExpressionKind::Index { left, index } => {
+            let left = eval_expression(left, env)?;
+            let index = eval_expression(index, env)?;
+            eval_index_expression(left, index).map_err(error)
+        }
+    }
+}
+
+/// How many calls to Monkey functions can be nested before a program fails with a stack
+/// overflow, in both the evaluator and the VM.
+pub const MAX_CALL_DEPTH: usize = 1024;
+
+/// How many bytes of native stack the evaluator uses at most unless told otherwise, which leaves
+/// room for the host on the 2 MiB stack that `std::thread::spawn` gives threads by default.
+/// The evaluator recurses on the native stack, taking a few kilobytes per call in release builds
+/// and several times that in debug builds. Reaching `MAX_CALL_DEPTH` takes a limit of about
+/// 4 MiB in release builds and 32 MiB in debug builds, on a thread with a bigger stack still.
+pub const DEFAULT_STACK_LIMIT: usize = 1 << 20;
+
+thread_local! {
+    /// The number of calls to Monkey functions being evaluated on this thread.
+    static CALL_DEPTH: Cell<usize> = const { Cell::new(0) };
+    /// Where the native stack ended when the outermost evaluation on this thread started, and
+    /// how many bytes past that evaluation may use.
+    static STACK: Cell<Option<(usize, usize)>> = const { Cell::new(None) };
+}
+
+/// Returns roughly where the native stack currently ends.
+#[inline(never)]
+fn stack_position() -> usize {
+    let marker = 0u8;
+    std::hint::black_box(&marker) as *const u8 as usize
+}
+
+/// Whether evaluation has used up the native stack it was given. Stacks grow down on most
+/// platforms and up on a few, so only the distance from the start counts.
+fn stack_exhausted() -> bool {
+    STACK
+        .get()
+        .is_some_and(|(start, limit)| start.abs_diff(stack_position()) > limit)
+}
+
+/// Marks the outermost evaluation on this thread while it is alive, so that nested evaluations
+/// measure the stack they use from where it started.
+struct StackGuard {
+    outermost: bool,
+}
+
+impl StackGuard {
+    fn enter(limit: usize) -> Self {
+        let outermost = STACK.get().is_none();
+        if outermost {
+            STACK.set(Some((stack_position(), limit)));
+        }
+        Self { outermost }
+    }
+}
+
+impl Drop for StackGuard {
+    fn drop(&mut self) {
+        if self.outermost {
+            STACK.set(None);
+        }
+    }
+}
+
+/// Calls `function` with `arguments`; `span` locates the call expression for errors.
+pub fn apply_function(
+    function: Object,
+    arguments: Vec<Object>,
+    span: Span,
+) -> Result<Object, EvalError> {
+    let function = match function {
+        Object::Function(function) => function,
+        Object::Builtin(builtin) => {
+            return builtin
+                .call(arguments)
+                .map_err(|kind| EvalError { kind, span })
+        }
+        other => {
+            return Err(EvalError {
+                kind: EvalErrorKind::NotAFunction(other.type_name()),
+                span,
+            })
+        }
+    };
+    if function.parameters.len() != arguments.len() {
+        return Err(EvalError {
+            kind: EvalErrorKind::WrongArgumentCount {
+                expected: function.parameters.len(),
+                found: arguments.len(),
+            },
+            span,
+        });
+    }
+    let _stack = StackGuard::enter(function.env.borrow().stack_limit);
+    let depth = CALL_DEPTH.get();
+    if depth >= MAX_CALL_DEPTH || stack_exhausted() {
+        return Err(EvalError {
+            kind: EvalErrorKind::StackOverflow,
+            span,
+        });
+    }
+    let mut scope = Environment::new_enclosed(Rc::clone(&function.env));
+    for (parameter, argument) in function.parameters.iter().zip(arguments) {
+        scope.set(&parameter.name, argument);
+    }
+    CALL_DEPTH.set(depth + 1);
+    let result = eval_statements(&function.body.statements, &Rc::new(RefCell::new(scope)));
+    CALL_DEPTH.set(depth);
+    match result {
+        Ok(value) | Err(Unwind::Return(value)) => Ok(value),
+        Err(Unwind::Error(error)) => Err(error),
+    }
+}
+
+/// Indexes into an array or hash; an index outside of the array or a missing key evaluates to
+/// `null`.
+pub(crate) fn eval_index_expression(left: Object, index: Object) -> Result<Object, EvalErrorKind> {
+    match (left, index) {
+        (Object::Array(elements), Object::Integer(index)) => Ok(usize::try_from(index)
+            .ok()
+            .and_then(|index| elements.get(index).cloned())
+            .unwrap_or(Object::Null)),
+        (Object::Hash(pairs), index) => Ok(pairs
+            .get(&index.hash_key()?)
+            .cloned()
+            .unwrap_or(Object::Null)),
+        (left, index) => Err(EvalErrorKind::IndexNotSupported {
+            left: left.type_name(),
+            index: index.type_name(),
+        }),
+    }
+}
+
+/// Monkey treats `false` and `null` as falsy and every other value as truthy.
+pub(crate) fn is_truthy(object: &Object) -> bool {
+    !matches!(object, Object::Boolean(false) | Object::Null)
+}
+
+pub(crate) fn eval_prefix_expression(
+    operator: PrefixOperator,
+    right: Object,
+) -> Result<Object, EvalErrorKind> {
+    match (operator, right) {
+        (PrefixOperator::Bang, right) => Ok(Object::Boolean(!is_truthy(&right))),
+        (PrefixOperator::Minus, Object::Integer(value)) => {
+            Ok(Object::Integer(value.wrapping_neg()))
+        }
+        (operator, right) => Err(EvalErrorKind::UnknownPrefixOperator {
+            operator,
+            right: right.type_name(),
+        }),
+    }
+}
+
+pub(crate) fn eval_infix_expression(
+    left: Object,
+    operator: InfixOperator,
+    right: Object,
+) -> Result<Object, EvalErrorKind> {
+    match (left, right) {
+        (Object::Integer(left), Object::Integer(right)) => {
+            eval_integer_infix_expression(left, operator, right)
+        }
+        (Object::String(left), Object::String(right)) => {
+            eval_string_infix_expression(left, operator, right)
+        }
+        (Object::Boolean(left), Object::Boolean(right)) if operator == InfixOperator::Equal => {
+            Ok(Object::Boolean(left == right))
+        }
+        (Object::Boolean(left), Object::Boolean(right)) if operator == InfixOperator::NotEqual => {
+            Ok(Object::Boolean(left != right))
+        }
+        (left, right) if left.type_name() != right.type_name() => {
+            Err(EvalErrorKind::TypeMismatch {
+                left: left.type_name(),
+                operator,
+                right: right.type_name(),
+            })
+        }
+        (left, right) => Err(EvalErrorKind::UnknownInfixOperator {
+            left: left.type_name(),
+            operator,
+            right: right.type_name(),
+        }),
+    }
+}
+
+fn eval_string_infix_expression(
+    left: String,
+    operator: InfixOperator,
+    right: String,
+) -> Result<Object, EvalErrorKind> {
+    match operator {
+        InfixOperator::Plus => Ok(Object::String(left + &right)),
+        InfixOperator::Equal => Ok(Object::Boolean(left == right)),
+        InfixOperator::NotEqual => Ok(Object::Boolean(left != right)),
+        _ => Err(EvalErrorKind::UnknownInfixOperator {
+            left: "STRING",
+            operator,
+            right: "STRING",
+        }),
+    }
+}
+
+fn eval_integer_infix_expression(
+    left: i64,
+    operator: InfixOperator,
+    right: i64,
+) -> Result<Object, EvalErrorKind> {
+    let result = match operator {
+        InfixOperator::Plus => Object::Integer(left.wrapping_add(right)),
+        InfixOperator::Minus => Object::Integer(left.wrapping_sub(right)),
+        InfixOperator::Asterisk => Object::Integer(left.wrapping_mul(right)),
+        InfixOperator::Slash if right == 0 => return Err(EvalErrorKind::DivisionByZero),
+        InfixOperator::Slash => Object::Integer(left.wrapping_div(right)),
+        InfixOperator::LessThan => Object::Boolean(left < right),
+        InfixOperator::GreaterThan => Object::Boolean(left > right),
+        InfixOperator::Equal => Object::Boolean(left == right),
+        InfixOperator::NotEqual => Object::Boolean(left != right),
+    };
+    Ok(result)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ast::Parser;
+    use crate::builtins::Arity;
+
+    fn run(input: &str, env: &Env) -> Result<Object, EvalError> {
+        eval(&Parser::new(input).parse().unwrap(), env)
+    }
+
+    fn new_env() -> Env {
+        Rc::new(RefCell::new(Environment::new()))
+    }
+
+    #[test]
+    fn let_binds_values() {
+        let env = new_env();
+        assert_eq!(run("let a = 5;", &env), Ok(Object::Null));
+        assert_eq!(run("let b = a;", &env), Ok(Object::Null));
+        assert_eq!(env.borrow().get("b"), Some(Object::Integer(5)));
+        assert_eq!(run("let t = true;", &env), Ok(Object::Null));
+        assert_eq!(env.borrow().get("t"), Some(Object::Boolean(true)));
+    }
+
+    #[test]
+    fn unknown_identifier_is_an_error() {
+        let env = new_env();
+        assert_eq!(
+            run("let a = foo;", &env).unwrap_err().kind,
+            EvalErrorKind::IdentifierNotFound("foo".to_string())
+        );
+    }
+
+    #[test]
+    fn prefix_and_infix_expressions() {
+        let cases = [
+            ("let x = -5;", Object::Integer(-5)),
+            ("let x = !true;", Object::Boolean(false)),
+            ("let x = !!5;", Object::Boolean(true)),
+            ("let x = 5 + 5 + 5 + 5 - 10;", Object::Integer(10)),
+            (
+                "let x = (5 + 10 * 2 + 15 / 3) * 2 + -10;",
+                Object::Integer(50),
+            ),
+            ("let x = 1 < 2;", Object::Boolean(true)),
+            ("let x = 1 != 1;", Object::Boolean(false)),
+            ("let x = (1 < 2) == true;", Object::Boolean(true)),
+            ("let x = true != false;", Object::Boolean(true)),
+        ];
+        for (input, expected) in cases {
+            let env = new_env();
+            run(input, &env).unwrap();
+            assert_eq!(env.borrow().get("x"), Some(expected), "{}", input);
+        }
+    }
+
+    #[test]
+    fn program_value_is_last_statement_or_return() {
+        let cases = [
+            ("5; 10", Object::Integer(10)),
+            ("let a = 2; a * 3", Object::Integer(6)),
+            ("9; return 2 * 5; 9;", Object::Integer(10)),
+            ("return true; 5", Object::Boolean(true)),
+            ("let a = 1;", Object::Null),
+        ];
+        for (input, expected) in cases {
+            let env = new_env();
+            assert_eq!(run(input, &env), Ok(expected), "{}", input);
+        }
+    }
+
+    #[test]
+    fn if_else_expressions() {
+        let cases = [
+            ("if (true) { 10 }", Object::Integer(10)),
+            ("if (false) { 10 }", Object::Null),
+            ("if (1) { 10 }", Object::Integer(10)),
+            ("if (1 > 2) { 10 } else { 20 }", Object::Integer(20)),
+            (
+                "if (if (false) { 1 }) { 10 } else { 20 }",
+                Object::Integer(20),
+            ),
+            (
+                "if (1 < 2) { if (true) { 5 } else { 6 } }",
+                Object::Integer(5),
+            ),
+        ];
+        for (input, expected) in cases {
+            let env = new_env();
+            assert_eq!(run(input, &env), Ok(expected), "{}", input);
+        }
+    }
+
+    #[test]
+    fn return_propagates_out_of_nested_blocks() {
+        let cases = [
+            (
+                "if (10 > 1) { if (10 > 1) { return 10; } return 1; }",
+                Object::Integer(10),
+            ),
+            (
+                "let f = fn(x) { if (x > 1) { if (true) { return x; } } 0 }; f(5) + f(1)",
+                Object::Integer(5),
+            ),
+            (
+                "let f = fn() { if (true) { return true; } false }; let r = f(); !r",
+                Object::Boolean(false),
+            ),
+            ("1 + if (true) { return 2; }", Object::Integer(2)),
+            (
+                "let f = fn() { let y = if (true) { return 1; }; 99 }; f()",
+                Object::Integer(1),
+            ),
+            (
+                "let f = fn() { 1 }; [f(), if (true) { return 9; }]",
+                Object::Integer(9),
+            ),
+        ];
+        for (input, expected) in cases {
+            let env = new_env();
+            assert_eq!(run(input, &env), Ok(expected), "{}", input);
+        }
+    }
+
+    #[test]
+    fn functions_and_closures() {
+        let cases = [
+            ("let identity = fn(x) { x; }; identity(5);", 5),
+            ("let identity = fn(x) { return x; 10; }; identity(5);", 5),
+            ("let add = fn(x, y) { x + y; }; add(5 + 5, add(5, 5));", 20),
+            ("fn(x) { x * 2; }(5)", 10),
+            (
+                "let newAdder = fn(x) { fn(y) { x + y } }; let addTwo = newAdder(2); addTwo(3);",
+                5,
+            ),
+            (
+                "let twice = fn(f, x) { f(f(x)) }; let inc = fn(x) { x + 1 }; twice(inc, 0);",
+                2,
+            ),
+            (
+                "let fact = fn(n) { if (n < 2) { 1 } else { n * fact(n - 1) } }; fact(5);",
+                120,
+            ),
+        ];
+        for (input, expected) in cases {
+            let env = new_env();
+            assert_eq!(run(input, &env), Ok(Object::Integer(expected)), "{}", input);
+        }
+    }
+
+    #[test]
+    fn strings() {
+        let cases = [
+            (
+                r#""hello world""#,
+                Object::String("hello world".to_string()),
+            ),
+            (
+                r#"let greet = fn(name) { "hi, " + name + "!" }; greet("bob")"#,
+                Object::String("hi, bob!".to_string()),
+            ),
+            (r#""a" == "a""#, Object::Boolean(true)),
+            (
+                r#"let name = "ann"; let age = 41; "hello ${name}, you are ${age + 1}""#,
+                Object::String("hello ann, you are 42".to_string()),
+            ),
+            (
+                r#"let f = fn(x) { "<${x}>" }; "${f("${1 < 2}")}!""#,
+                Object::String("<true>!".to_string()),
+            ),
+            (r#""a" != "a""#, Object::Boolean(false)),
+        ];
+        for (input, expected) in cases {
+            let env = new_env();
+            assert_eq!(run(input, &env), Ok(expected), "{}", input);
+        }
+    }
+
+    #[test]
+    fn operator_errors() {
+        let cases = [
+            ("let x = 5 + true;", "type mismatch: INTEGER + BOOLEAN"),
+            ("let x = -true;", "unknown operator: -BOOLEAN"),
+            (
+                "let x = true + false;",
+                "unknown operator: BOOLEAN + BOOLEAN",
+            ),
+            ("let x = 1 / 0;", "division by zero"),
+            (r#""a" - "b""#, "unknown operator: STRING - STRING"),
+            (r#""a" + 1"#, "type mismatch: STRING + INTEGER"),
+            (r#""${missing}""#, "identifier not found: missing"),
+            ("let x = 1(2);", "not a function: INTEGER"),
+            (
+                "fn(x) { x }(1, 2)",
+                "wrong number of arguments: expected 1, found 2",
+            ),
+            ("let f = fn(x) { x }; f(1); x", "identifier not found: x"),
+        ];
+        for (input, expected) in cases {
+            let env = new_env();
+            assert_eq!(run(input, &env).unwrap_err().to_string(), expected);
+        }
+    }
+
+    #[test]
+    fn arrays_and_builtins() {
+        let cases = [
+            ("[1, 2 * 2, 3 + 3]", "[1, 4, 6]"),
+            ("let a = [1, 2, 3]; a[0] + a[1] + a[2]", "6"),
+            ("let i = 0; [1][i]", "1"),
+            ("[1, 2, 3][3]", "null"),
+            ("[1, 2, 3][-1]", "null"),
+            (r#"[fn(x) { x * 2 }][0](4)"#, "8"),
+            (r#"len("") + len("four") + len("héllo")"#, "9"),
+            ("len([1, 2, 3])", "3"),
+            ("first([1, 2])", "1"),
+            ("first([])", "null"),
+            ("last([1, 2])", "2"),
+            ("rest([1, 2, 3])", "[2, 3]"),
+            ("rest([])", "null"),
+            (
+                r#"let a = ["a"]; let b = push(a, "b"); [a, b]"#,
+                r#"[["a"], ["a", "b"]]"#,
+            ),
+            ("len", "builtin function len"),
+            (
+                "let map = fn(arr, f) { let iter = fn(arr, acc) { if (len(arr) == 0) { acc } \
+                 else { iter(rest(arr), push(acc, f(first(arr)))) } }; iter(arr, []) }; \
+                 map([1, 2, 3], fn(x) { x * x })",
+                "[1, 4, 9]",
+            ),
+        ];
+        for (input, expected) in cases {
+            let env = new_env();
+            assert_eq!(run(input, &env).unwrap().to_string(), expected, "{}", input);
+        }
+
+        let errors = [
+            ("len(1)", "argument to `len` not supported, got INTEGER"),
+            (
+                r#"len("one", "two")"#,
+                "wrong number of arguments: expected 1, found 2",
+            ),
+            (
+                "push(1, 1)",
+                "argument to `push` not supported, got INTEGER",
+            ),
+            ("1[0]", "index operator not supported: INTEGER[INTEGER]"),
+            ("[1][true]", "index operator not supported: ARRAY[BOOLEAN]"),
+        ];
+        for (input, expected) in errors {
+            let env = new_env();
+            assert_eq!(run(input, &env).unwrap_err().to_string(), expected);
+        }
+    }
+
+    #[test]
+    fn conversion_builtins() {
+        let cases = [
+            (
+                r#"type(1) + type("") + type([]) + type(len)"#,
+                "INTEGERSTRINGARRAYBUILTIN",
+            ),
+            (r#"str(12) + str(true) + str("s")"#, "12trues"),
+            (r#"str([1, "a"])"#, r#"[1, "a"]"#),
+            (r#"int(" 42 ") + int(-1)"#, "41"),
+            ("puts(1, 2)", "null"),
+            ("let len = fn(x) { 0 }; len([1])", "0"),
+        ];
+        for (input, expected) in cases {
+            let env = new_env();
+            assert_eq!(run(input, &env).unwrap().to_string(), expected, "{}", input);
+        }
+        assert_eq!(
+            run(r#"int("4x")"#, &new_env()).unwrap_err().to_string(),
+            r#"could not convert "4x" to an integer"#
+        );
+    }
+
+    #[test]
+    fn host_defined_builtins() {
+        let mut builtins = Builtins::empty();
+        builtins.register("add", Arity::Exactly(2), |arguments| {
+            match (&arguments[0], &arguments[1]) {
+                (Object::Integer(a), Object::Integer(b)) => Ok(Object::Integer(a + b)),
+                _ => Err(EvalErrorKind::Custom("add expects integers".to_string())),
+            }
+        });
+        let env = Rc::new(RefCell::new(Environment::with_builtins(builtins)));
+        assert_eq!(
+            run("let f = fn(x) { add(x, 1) }; f(2)", &env),
+            Ok(Object::Integer(3))
+        );
+        assert_eq!(
+            run("add(1, true)", &env).unwrap_err().to_string(),
+            "add expects integers"
+        );
+        assert_eq!(
+            run("add(1)", &env).unwrap_err().to_string(),
+            "wrong number of arguments: expected 2, found 1"
+        );
+        assert_eq!(
+            run("len([])", &env).unwrap_err().to_string(),
+            "identifier not found: len"
+        );
+    }
+
+    #[test]
+    fn hashes() {
+        let cases = [
+            (r#"{}"#, "{}"),
+            (
+                r#"let two = "two"; {"one": 10 - 9, two: 1 + 1, "thr" + "ee": 6 / 2, 4: 4, true: 5}"#,
+                r#"{4: 4, true: 5, "one": 1, "three": 3, "two": 2}"#,
+            ),
+            (r#"{"a": 1, "a": 2}["a"]"#, "2"),
+            (r#"{"foo": 5}["bar"]"#, "null"),
+            (r#"let key = "foo"; {"foo": 5}[key]"#, "5"),
+            ("{5: 5}[5]", "5"),
+            ("{true: 5}[1 < 2]", "5"),
+            (r#"{"list": [1, "2"]}"#, r#"{"list": [1, "2"]}"#),
+            (r#"{"f": fn(x) { x * 3 }}["f"](2)"#, "6"),
+        ];
+        for (input, expected) in cases {
+            let env = new_env();
+            assert_eq!(run(input, &env).unwrap().to_string(), expected, "{}", input);
+        }
+
+        let errors = [
+            (
+                r#"{"name": "Monkey"}[fn(x) { x }]"#,
+                "unusable as hash key: FUNCTION",
+            ),
+            ("{[1]: 2}", "unusable as hash key: ARRAY"),
+            ("{{}: 2}", "unusable as hash key: HASH"),
+        ];
+        for (input, expected) in errors {
+            let env = new_env();
+            assert_eq!(run(input, &env).unwrap_err().to_string(), expected);
+        }
+        let error = run("let h = {1: 1, len: 2};", &new_env()).unwrap_err();
+        assert_eq!((error.span.start, error.span.end), (15, 18));
+    }
+
+    #[test]
+    fn errors_point_at_the_failing_expression() {
+        let env = new_env();
+        let error = run("let f = fn(x) {\n  x + true\n};\nf(1)", &env).unwrap_err();
+        assert_eq!(
+            error.span,
+            Span {
+                start: 18,
+                end: 26,
+                line: 2,
+                column: 3
+            }
+        );
+        let error = run("1 + 2;\nf(1, 2)", &env).unwrap_err();
+        assert_eq!((error.span.start, error.span.end), (7, 14));
+        assert_eq!((error.span.line, error.span.column), (2, 1));
+    }
+
+    #[test]
+    fn deep_recursion_overflows() {
+        // Test threads have a small stack, and debug builds need far more per call.
+        let thread = std::thread::Builder::new().stack_size(256 << 20);
+        let handle = thread.spawn(|| {
+            let env = new_env();
+            env.borrow_mut().set_stack_limit(128 << 20);
+            let error = run("let f = fn(x) { f(x + 1) }; f(0)", &env).unwrap_err();
+            assert_eq!(error.kind, EvalErrorKind::StackOverflow);
+            assert_eq!((error.span.start, error.span.end), (16, 24));
+            let input = "let f = fn(n) { if (n == 0) { 0 } else { 1 + f(n - 1) } }; f(1000)";
+            assert_eq!(run(input, &env), Ok(Object::Integer(1000)));
+        });
+        handle.unwrap().join().unwrap();
+    }
+
+    #[test]
+    fn recursion_stops_within_the_default_stack() {
+        let handle = std::thread::spawn(|| {
+            let env = new_env();
+            let error = run("let f = fn(x) { f(x + 1) }; f(0)", &env).unwrap_err();
+            assert_eq!(error.kind, EvalErrorKind::StackOverflow);
+        });
+        handle.join().unwrap();
+    }
+}
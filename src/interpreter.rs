@@ -0,0 +1,396 @@
+use crate::ast::{ParseError, Parser};
+use crate::builtins::Builtins;
+use crate::eval::{self, Env, Environment, EvalError, Object};
+use crate::token::Span;
+use std::cell::RefCell;
+use std::fmt;
+use std::rc::Rc;
+
+/// Runs Monkey code on behalf of a Rust program, keeping its global bindings between calls.
+///
+/// ```
+/// use interpreter::Interpreter;
+///
+/// let mut interpreter = Interpreter::new();
+/// interpreter.set_global("base", 10);
+/// interpreter.eval_str("let add = fn(x) { base + x };").unwrap();
+/// let sum: i64 = interpreter.call_function("add", [5.into()]).unwrap().try_into().unwrap();
+/// assert_eq!(sum, 15);
+/// ```
+///
+/// The interpreter evaluates on the stack of the calling thread. It reports a stack overflow
+/// rather than exhausting it, using at most `eval::DEFAULT_STACK_LIMIT` bytes, which is safe on
+/// threads with the 2 MiB stack that `std::thread::spawn` gives by default. Deeply recursive
+/// programs need a thread with a bigger stack and a matching `with_stack_limit`.
+#[derive(Debug)]
+pub struct Interpreter {
+    env: Env,
+}
+
+impl Interpreter {
+    /// Creates an interpreter with the standard builtins.
+    pub fn new() -> Self {
+        Self::with_builtins(Builtins::standard())
+    }
+
+    /// Creates an interpreter whose programs can call `builtins` instead of the standard ones.
+    pub fn with_builtins(builtins: Builtins) -> Self {
+        Self {
+            env: Rc::new(RefCell::new(Environment::with_builtins(builtins))),
+        }
+    }
+
+    /// Lets evaluation use up to `bytes` of the native stack before it fails with a stack
+    /// overflow. Leave a few hundred kilobytes of the thread's stack to spare for the host and
+    /// the parser.
+    pub fn with_stack_limit(self, bytes: usize) -> Self {
+        self.env.borrow_mut().set_stack_limit(bytes);
+        self
+    }
+
+    /// Parses and evaluates `source`, returning the value of its last statement.
+    pub fn eval_str(&mut self, source: &str) -> Result<Object, Error> {
+        let program = Parser::new(source).parse().map_err(Error::Parse)?;
+        Ok(eval::eval(&program, &self.env)?)
+    }
+
+    /// Binds `name` to `value`, as a top-level `let` statement would.
+    pub fn set_global(&mut self, name: &str, value: impl Into<Object>) {
+        self.env.borrow_mut().set(name, value.into());
+    }
+
+    /// Returns the value bound to `name`, converted to `T`.
+    pub fn get_global<T>(&self, name: &str) -> Result<T, Error>
+    where
+        T: TryFrom<Object, Error = ConversionError>,
+    {
+        Ok(T::try_from(self.global(name)?)?)
+    }
+
+    /// Returns the value bound to `name` as it is, for values that don't convert to a Rust type,
+    /// such as functions and hashes.
+    pub fn get_global_object(&self, name: &str) -> Result<Object, Error> {
+        self.global(name)
+    }
+
+    /// Calls the function or builtin bound to `name`.
+    pub fn call_function(
+        &mut self,
+        name: &str,
+        arguments: impl IntoIterator<Item = Object>,
+    ) -> Result<Object, Error> {
+        let function = self.global(name)?;
+        let arguments = arguments.into_iter().collect();
+        // The call doesn't appear in any source, so errors raised by it have an empty span.
+        Ok(eval::apply_function(function, arguments, Span::default())?)
+    }
+
+    fn global(&self, name: &str) -> Result<Object, Error> {
+        self.env
+            .borrow()
+            .get(name)
+            .ok_or_else(|| Error::UndefinedGlobal(name.to_string()))
+    }
+}
+
+impl Default for Interpreter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Why an `Interpreter` call failed.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum Error {
+    Parse(Vec<ParseError>),
+    Eval(EvalError),
+    UndefinedGlobal(String),
+    Conversion(ConversionError),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Parse(errors) => {
+                let errors: Vec<String> = errors.iter().map(|e| e.to_string()).collect();
+                write!(f, "{}", errors.join("; "))
+            }
+            Error::Eval(error) => write!(f, "{}", error),
+            Error::UndefinedGlobal(name) => write!(f, "undefined global: {}", name),
+            Error::Conversion(error) => write!(f, "{}", error),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Parse(errors) => errors.first().map(|e| e as _),
+            Error::Eval(error) => Some(error),
+            Error::UndefinedGlobal(_) => None,
+            Error::Conversion(error) => Some(error),
+        }
+    }
+}
+
+impl From<EvalError> for Error {
+    fn from(error: EvalError) -> Self {
+        Error::Eval(error)
+    }
+}
+
+impl From<ConversionError> for Error {
+    fn from(error: ConversionError) -> Self {
+        Error::Conversion(error)
+    }
+}
+
+/// A Monkey value didn't have the type a Rust value was requested as.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct ConversionError {
+    pub expected: &'static str,
+    pub found: &'static str,
+}
+
+impl fmt::Display for ConversionError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "expected {}, found {}", self.expected, self.found)
+    }
+}
+
+impl std::error::Error for ConversionError {}
+
+impl From<i64> for Object {
+    fn from(value: i64) -> Self {
+        Object::Integer(value)
+    }
+}
+
+impl From<bool> for Object {
+    fn from(value: bool) -> Self {
+        Object::Boolean(value)
+    }
+}
+
+impl From<String> for Object {
+    fn from(value: String) -> Self {
+        Object::String(value)
+    }
+}
+
+impl From<&str> for Object {
+    fn from(value: &str) -> Self {
+        Object::String(value.to_string())
+    }
+}
+
+impl From<()> for Object {
+    fn from(_: ()) -> Self {
+        Object::Null
+    }
+}
+
+/// `None` becomes `null`.
+impl<T: Into<Object>> From<Option<T>> for Object {
+    fn from(value: Option<T>) -> Self {
+        value.map_or(Object::Null, Into::into)
+    }
+}
+
+impl<T: Into<Object>> From<Vec<T>> for Object {
+    fn from(values: Vec<T>) -> Self {
+        Object::Array(values.into_iter().map(Into::into).collect())
+    }
+}
+
+impl TryFrom<Object> for i64 {
+    type Error = ConversionError;
+
+    fn try_from(value: Object) -> Result<Self, Self::Error> {
+        match value {
+            Object::Integer(value) => Ok(value),
+            other => Err(ConversionError {
+                expected: "INTEGER",
+                found: other.type_name(),
+            }),
+        }
+    }
+}
+
+impl TryFrom<Object> for bool {
+    type Error = ConversionError;
+
+    fn try_from(value: Object) -> Result<Self, Self::Error> {
+        match value {
+            Object::Boolean(value) => Ok(value),
+            other => Err(ConversionError {
+                expected: "BOOLEAN",
+                found: other.type_name(),
+            }),
+        }
+    }
+}
+
+impl TryFrom<Object> for String {
+    type Error = ConversionError;
+
+    fn try_from(value: Object) -> Result<Self, Self::Error> {
+        match value {
+            Object::String(value) => Ok(value),
+            other => Err(ConversionError {
+                expected: "STRING",
+                found: other.type_name(),
+            }),
+        }
+    }
+}
+
+impl TryFrom<Object> for () {
+    type Error = ConversionError;
+
+    fn try_from(value: Object) -> Result<Self, Self::Error> {
+        match value {
+            Object::Null => Ok(()),
+            other => Err(ConversionError {
+                expected: "NULL",
+                found: other.type_name(),
+            }),
+        }
+    }
+}
+
+/// Converts an array element by element.
+impl<T: TryFrom<Object, Error = ConversionError>> TryFrom<Object> for Vec<T> {
+    type Error = ConversionError;
+
+    fn try_from(value: Object) -> Result<Self, Self::Error> {
+        match value {
+            Object::Array(elements) => elements.into_iter().map(T::try_from).collect(),
+            other => Err(ConversionError {
+                expected: "ARRAY",
+                found: other.type_name(),
+            }),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::builtins::Arity;
+    use crate::eval::EvalErrorKind;
+
+    #[test]
+    fn globals_persist_between_calls() {
+        let mut interpreter = Interpreter::new();
+        interpreter.set_global("names", vec!["ann", "bob"]);
+        interpreter
+            .eval_str(r#"let greeting = "hi " + first(names); let count = len(names);"#)
+            .unwrap();
+        assert_eq!(
+            interpreter.get_global::<String>("greeting"),
+            Ok("hi ann".to_string())
+        );
+        assert_eq!(interpreter.get_global::<i64>("count"), Ok(2));
+        assert_eq!(
+            interpreter.get_global::<Vec<String>>("names"),
+            Ok(vec!["ann".to_string(), "bob".to_string()])
+        );
+        assert_eq!(
+            interpreter.get_global::<bool>("count"),
+            Err(Error::Conversion(ConversionError {
+                expected: "BOOLEAN",
+                found: "INTEGER"
+            }))
+        );
+        assert_eq!(
+            interpreter.get_global::<i64>("missing"),
+            Err(Error::UndefinedGlobal("missing".to_string()))
+        );
+    }
+
+    #[test]
+    fn returns_raw_globals() {
+        let mut interpreter = Interpreter::new();
+        interpreter
+            .eval_str(r#"let double = fn(x) { x * 2 }; let ages = {"ann": 30};"#)
+            .unwrap();
+        let double = interpreter.get_global_object("double").unwrap();
+        assert_eq!(double.to_string(), "fn(x) { (x * 2) }");
+        let ages = interpreter.get_global_object("ages").unwrap();
+        assert_eq!(ages.to_string(), r#"{"ann": 30}"#);
+        assert_eq!(
+            interpreter.get_global_object("missing"),
+            Err(Error::UndefinedGlobal("missing".to_string()))
+        );
+    }
+
+    #[test]
+    fn calls_functions_and_host_builtins() {
+        let mut builtins = Builtins::standard();
+        builtins.register("shout", Arity::Exactly(1), |arguments| {
+            let text = String::try_from(arguments[0].clone())
+                .map_err(|error| EvalErrorKind::Custom(error.to_string()))?;
+            Ok(Object::from(text.to_uppercase()))
+        });
+        let mut interpreter = Interpreter::with_builtins(builtins);
+        interpreter
+            .eval_str(
+                r#"let greet = fn(name, excited) { if (excited) { shout(name) } else { name } };"#,
+            )
+            .unwrap();
+
+        let greeting = interpreter.call_function("greet", ["ann".into(), true.into()]);
+        assert_eq!(greeting, Ok(Object::from("ANN")));
+        assert_eq!(
+            interpreter.call_function("len", [Object::from(vec![1, 2, 3])]),
+            Ok(Object::Integer(3))
+        );
+        assert_eq!(
+            interpreter
+                .call_function("greet", [Object::Null, true.into()])
+                .unwrap_err()
+                .to_string(),
+            "expected STRING, found NULL"
+        );
+        assert_eq!(
+            interpreter
+                .call_function("greet", [Object::Null])
+                .unwrap_err()
+                .to_string(),
+            "wrong number of arguments: expected 2, found 1"
+        );
+    }
+
+    #[test]
+    fn reports_parse_and_runtime_errors() {
+        let mut interpreter = Interpreter::new();
+        let Err(Error::Parse(errors)) = interpreter.eval_str("let = 1; let x 2;") else {
+            panic!("expected parse errors");
+        };
+        assert_eq!(errors.len(), 2);
+        let Err(Error::Eval(error)) = interpreter.eval_str("1 + true") else {
+            panic!("expected a runtime error");
+        };
+        assert_eq!(error.to_string(), "type mismatch: INTEGER + BOOLEAN");
+    }
+
+    #[test]
+    fn deep_recursion_fails_on_a_default_thread() {
+        let handle = std::thread::spawn(|| {
+            let mut interpreter = Interpreter::new();
+            let source = "let f = fn(n) { if (n == 0) { 0 } else { 1 + f(n - 1) } };";
+            let Err(Error::Eval(error)) = interpreter.eval_str(&format!("{} f(800)", source))
+            else {
+                panic!("expected a stack overflow");
+            };
+            assert_eq!(error.kind, EvalErrorKind::StackOverflow);
+            let Err(Error::Eval(error)) = interpreter.call_function("f", [100_000.into()]) else {
+                panic!("expected a stack overflow");
+            };
+            assert_eq!(error.kind, EvalErrorKind::StackOverflow);
+            assert_eq!(interpreter.call_function("f", [10.into()]), Ok(10.into()));
+        });
+        handle.join().unwrap();
+    }
+}
@@ -1,41 +1,123 @@
-use crate::token::Token;
+use crate::token::{OwnedToken, Span, SpannedToken, StringPart, Token};
+use std::borrow::Cow;
 
+/// The reserved words of the language and the tokens they are lexed into.
+pub const KEYWORDS: [(&str, OwnedToken); 7] = [
+    ("fn", Token::Function),
+    ("let", Token::Let),
+    ("if", Token::If),
+    ("else", Token::Else),
+    ("true", Token::True),
+    ("false", Token::False),
+    ("return", Token::Return),
+];
+
+/// Splits source code into tokens that borrow their text from it.
 #[derive(Debug)]
-pub struct Lexer {
-    input: Box<[u8]>,
+pub struct Lexer<'src> {
+    input: &'src str,
     position: usize,
     read_position: usize,
     ch: u8,
+    line: usize,
+    line_start: usize,
+    /// Narrows the span of an erroneous token down to the part at fault, e.g. the opening quote
+    /// of an unterminated string.
+    error_span: Option<Span>,
+    /// Where `input` starts in the enclosing source, so that the code embedded in an interpolated
+    /// string is lexed with spans that point into the whole source.
+    origin: Span,
+    /// An empty span right after the last token read, if any.
+    last_end: Option<Span>,
 }
 
-impl Lexer {
+impl<'src> Lexer<'src> {
     /// Creates a new `Lexer` from a `&str`.
-    ///
-    /// The `&str` is transformed into a `Box<[u8]>` internally.
-    pub fn new(input: &str) -> Lexer {
+    pub fn new(input: &'src str) -> Self {
+        Self::new_at(
+            input,
+            Span {
+                start: 0,
+                end: 0,
+                line: 1,
+                column: 1,
+            },
+        )
+    }
+
+    /// Creates a `Lexer` for `input` found at `origin` within a larger source.
+    pub fn new_at(input: &'src str, origin: Span) -> Self {
         let mut lexer = Lexer {
-            input: Box::from(input.as_bytes()),
+            input,
             position: 0,
             read_position: 0,
             ch: 0,
+            line: 1,
+            line_start: 0,
+            error_span: None,
+            origin,
+            last_end: None,
         };
         lexer.read_char();
         lexer
     }
 
     /// Returns the next token or `None` if reached the end.
+    pub fn next_token(&mut self) -> Option<Token<'src>> {
+        self.next().map(|token| token.token)
+    }
+
+    /// Returns the next token along with its location, or `Token::Eof` once the end is reached.
     ///
-    /// NOTE: Consider switching from `Option<Token>` back to `Token` and returning `Token::Eof`
-    /// instead of `None`.
-    pub fn next_token(&mut self) -> Option<Token> {
+    /// `Token::Eof` is located right after the last token rather than after any trailing
+    /// whitespace, so that a premature end of input is reported where the code stops.
+    pub fn next_spanned_token(&mut self) -> SpannedToken<'src> {
         self.skip_whitespace();
+        let start = self.start_span();
+        let token = self.read_token();
+        if token == Token::Eof {
+            let span = self.last_end.unwrap_or(start);
+            return SpannedToken { token, span };
+        }
+        let span = match self.error_span.take() {
+            Some(span) => span,
+            None => self.end_span(start),
+        };
+        self.last_end = Some(self.start_span());
+        SpannedToken { token, span }
+    }
+
+    /// Returns an empty span at the current position, to be completed with `end_span`.
+    fn start_span(&self) -> Span {
+        let column = if self.line == 1 {
+            self.origin.column + self.position
+        } else {
+            self.position - self.line_start + 1
+        };
+        Span {
+            start: self.origin.start + self.position,
+            end: self.origin.start + self.position,
+            line: self.origin.line + self.line - 1,
+            column,
+        }
+    }
+
+    /// Extends `start` up to the current position.
+    fn end_span(&self, start: Span) -> Span {
+        Span {
+            end: self.origin.start + self.position.min(self.input.len()),
+            ..start
+        }
+    }
 
+    fn read_token(&mut self) -> Token<'src> {
         let token = match self.ch {
             b'a'..=b'z' | b'A'..=b'Z' | b'_' => {
                 let identifier = self.read_identifier();
-                return Some(self.lookup_identifier(identifier));
+                return self.lookup_identifier(identifier);
             }
-            b'0'..=b'9' => return Some(Token::Int(self.read_number())),
+            b'0'..=b'9' => return Token::Int(Cow::Borrowed(self.read_number())),
+            b'"' => return self.read_string(),
             b'=' => {
                 if self.peek_char() == b'=' {
                     self.read_char();
@@ -52,38 +134,231 @@ impl Lexer {
                     Token::Bang
                 }
             }
-            0 => return None,
-            _ => Token::from(self.ch),
+            0 => return Token::Eof,
+            // The lexer works on bytes, so each byte of a non-ASCII character is illegal on its
+            // own and can't be borrowed.
+            ch if !ch.is_ascii() => Token::Illegal(Cow::Borrowed("\u{FFFD}")),
+            ch => match Token::from(ch) {
+                Token::Illegal(_) => Token::Illegal(Cow::Borrowed(
+                    &self.input[self.position..self.read_position],
+                )),
+                token => token,
+            },
         };
         self.read_char();
-        Some(token)
+        token
     }
 
     /// Stores the next byte in `self.ch` and advances both the `position` and `read_position`.
     fn read_char(&mut self) {
+        if self.ch == b'\n' {
+            self.line += 1;
+            self.line_start = self.read_position;
+        }
         self.ch = if self.read_position >= self.input.len() {
             0
         } else {
-            self.input[self.read_position]
+            self.input.as_bytes()[self.read_position]
         };
         self.position = self.read_position;
         self.read_position += 1;
     }
 
-    fn read_identifier(&mut self) -> String {
+    /// Returns the input from `start` up to the current position.
+    fn slice(&self, start: usize) -> &'src str {
+        &self.input[start..self.position.min(self.input.len())]
+    }
+
+    fn read_identifier(&mut self) -> &'src str {
         let position = self.position;
         while self.ch.is_ascii_alphabetic() || self.ch == b'_' {
             self.read_char();
         }
-        String::from_utf8_lossy(&self.input[position..self.position]).to_string()
+        self.slice(position)
     }
 
-    fn read_number(&mut self) -> String {
+    fn read_number(&mut self) -> &'src str {
         let position = self.position;
         while self.ch.is_ascii_digit() {
             self.read_char();
         }
-        String::from_utf8_lossy(&self.input[position..self.position]).to_string()
+        self.slice(position)
+    }
+
+    /// Reads a double-quoted string literal, resolving its escape sequences. A string containing
+    /// `${...}` is returned as `Token::Interpolated`, with the embedded code left for the parser.
+    /// Text without escape sequences is borrowed from the input.
+    ///
+    /// Returns `Token::UnterminatedString` if the input ends before the closing quote, and
+    /// `Token::InvalidEscape` for the first unknown escape sequence in an otherwise complete string.
+    fn read_string(&mut self) -> Token<'src> {
+        let quote = self.start_span();
+        self.read_char();
+        let mut parts: Vec<StringPart> = Vec::new();
+        let mut value = Literal::new(self.position);
+        let mut invalid: Option<(&str, Span)> = None;
+        loop {
+            match self.ch {
+                b'"' => break,
+                0 => {
+                    self.error_span = Some(Span {
+                        end: quote.start + 1,
+                        ..quote
+                    });
+                    return Token::UnterminatedString;
+                }
+                b'\\' => {
+                    let start = self.start_span();
+                    let position = self.position;
+                    let text = value.resolved(self.input, position);
+                    self.read_char();
+                    match self.read_escape() {
+                        Some(ch) => text.push(ch),
+                        None if invalid.is_none() => {
+                            invalid = Some((self.slice(position), self.end_span(start)));
+                        }
+                        None => {}
+                    }
+                    value.start = self.position;
+                }
+                b'$' if self.peek_char() == b'{' => {
+                    let end = self.position;
+                    self.read_char();
+                    self.read_char();
+                    let start = self.start_span();
+                    let position = self.position;
+                    if !self.skip_interpolation() {
+                        continue;
+                    }
+                    let source = Cow::Borrowed(self.slice(position));
+                    let span = self.end_span(start);
+                    self.read_char();
+                    if let Some(literal) = value.take(self.input, end) {
+                        parts.push(StringPart::Literal(literal));
+                    }
+                    value = Literal::new(self.position);
+                    parts.push(StringPart::Code { source, span });
+                }
+                _ => self.read_char(),
+            }
+        }
+        let end = self.position;
+        self.read_char();
+        match invalid {
+            Some((text, span)) => {
+                self.error_span = Some(span);
+                Token::InvalidEscape(Cow::Borrowed(text))
+            }
+            None if parts.is_empty() => Token::Str(value.take(self.input, end).unwrap_or_default()),
+            None => {
+                if let Some(literal) = value.take(self.input, end) {
+                    parts.push(StringPart::Literal(literal));
+                }
+                Token::Interpolated(parts)
+            }
+        }
+    }
+
+    /// Advances to the `}` closing the `${` that was just read, stepping over nested braces and
+    /// strings. Returns `false` if the input ends first.
+    fn skip_interpolation(&mut self) -> bool {
+        let mut depth = 0;
+        loop {
+            match self.ch {
+                0 => return false,
+                b'}' if depth == 0 => return true,
+                b'}' => depth -= 1,
+                b'{' => depth += 1,
+                b'"' => {
+                    if !self.skip_string() {
+                        return false;
+                    }
+                    continue;
+                }
+                _ => {}
+            }
+            self.read_char();
+        }
+    }
+
+    /// Advances past the string literal starting at the current `"`, including any code
+    /// interpolated into it. Returns `false` if the input ends first.
+    fn skip_string(&mut self) -> bool {
+        self.read_char();
+        loop {
+            match self.ch {
+                0 => return false,
+                b'"' => {
+                    self.read_char();
+                    return true;
+                }
+                b'\\' => {
+                    self.read_char();
+                    if self.ch == 0 {
+                        return false;
+                    }
+                }
+                b'$' if self.peek_char() == b'{' => {
+                    self.read_char();
+                    self.read_char();
+                    if !self.skip_interpolation() {
+                        return false;
+                    }
+                }
+                _ => {}
+            }
+            self.read_char();
+        }
+    }
+
+    /// Reads the escape sequence after a `\\`, consuming as much of it as is valid.
+    fn read_escape(&mut self) -> Option<char> {
+        let ch = match self.ch {
+            b'n' => '\n',
+            b't' => '\t',
+            b'"' => '"',
+            b'\\' => '\\',
+            b'$' => '$',
+            b'u' => return self.read_unicode_escape(),
+            // Leave the end of the input for `read_string` to report.
+            0 => return None,
+            // Step over the whole character, so that the input is never split inside one.
+            _ => {
+                let len = self.input[self.position..]
+                    .chars()
+                    .next()
+                    .map_or(1, char::len_utf8);
+                for _ in 0..len {
+                    self.read_char();
+                }
+                return None;
+            }
+        };
+        self.read_char();
+        Some(ch)
+    }
+
+    /// Reads the `u{...}` part of a `\\u{...}` escape, with one to six hex digits.
+    fn read_unicode_escape(&mut self) -> Option<char> {
+        self.read_char();
+        if self.ch != b'{' {
+            return None;
+        }
+        self.read_char();
+        let start = self.position;
+        while self.ch.is_ascii_hexdigit() {
+            self.read_char();
+        }
+        let end = self.position;
+        if self.ch != b'}' {
+            return None;
+        }
+        self.read_char();
+        if start == end || end - start > 6 {
+            return None;
+        }
+        let digits = &self.input[start..end];
+        char::from_u32(u32::from_str_radix(digits, 16).ok()?)
     }
 
     fn skip_whitespace(&mut self) {
@@ -97,22 +372,63 @@ impl Lexer {
         if self.read_position >= self.input.len() {
             0
         } else {
-            self.input[self.read_position]
+            self.input.as_bytes()[self.read_position]
         }
     }
 
-    fn lookup_identifier(&self, identifier: String) -> Token {
-        match identifier.as_str() {
-            "fn" => Token::Function,
-            "let" => Token::Let,
-            "if" => Token::If,
-            "else" => Token::Else,
-            "true" => Token::True,
-            "false" => Token::False,
-            "return" => Token::Return,
-            _ => Token::Ident(identifier),
+    fn lookup_identifier(&self, identifier: &'src str) -> Token<'src> {
+        match KEYWORDS.iter().find(|(keyword, _)| *keyword == identifier) {
+            Some((_, token)) => token.clone(),
+            None => Token::Ident(Cow::Borrowed(identifier)),
+        }
+    }
+}
+
+/// Yields the tokens of the input along with their locations, stopping before `Token::Eof`.
+impl<'src> Iterator for Lexer<'src> {
+    type Item = SpannedToken<'src>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let token = self.next_spanned_token();
+        (token.token != Token::Eof).then_some(token)
+    }
+}
+
+/// The text of a string literal being read, which is borrowed from the input until an escape
+/// sequence makes it differ.
+struct Literal {
+    /// Where the text not yet copied into `resolved` starts in the input.
+    start: usize,
+    resolved: Option<String>,
+}
+
+impl Literal {
+    fn new(start: usize) -> Self {
+        Self {
+            start,
+            resolved: None,
         }
     }
+
+    /// Copies the text read so far, up to `end`, and returns it to append a resolved escape to.
+    fn resolved(&mut self, input: &str, end: usize) -> &mut String {
+        let resolved = self.resolved.get_or_insert_with(String::new);
+        resolved.push_str(&input[self.start..end]);
+        resolved
+    }
+
+    /// Returns the text up to `end`, or `None` if it is empty.
+    fn take<'src>(&mut self, input: &'src str, end: usize) -> Option<Cow<'src, str>> {
+        let text = match self.resolved.take() {
+            Some(mut resolved) => {
+                resolved.push_str(&input[self.start..end]);
+                Cow::Owned(resolved)
+            }
+            None => Cow::Borrowed(&input[self.start..end]),
+        };
+        self.start = end;
+        (!text.is_empty()).then_some(text)
+    }
 }
 
 #[cfg(test)]
@@ -123,58 +439,58 @@ mod tests {
     fn it_works() {
         let expected_tokens = vec![
             Token::Let,
-            Token::Ident(String::from("five")),
+            Token::Ident("five".into()),
             Token::Assign,
-            Token::Int(String::from("5")),
+            Token::Int("5".into()),
             Token::Semicolon,
             Token::Let,
-            Token::Ident(String::from("ten")),
+            Token::Ident("ten".into()),
             Token::Assign,
-            Token::Int(String::from("10")),
+            Token::Int("10".into()),
             Token::Semicolon,
             Token::Let,
-            Token::Ident(String::from("add")),
+            Token::Ident("add".into()),
             Token::Assign,
             Token::Function,
             Token::LParen,
-            Token::Ident(String::from("x")),
+            Token::Ident("x".into()),
             Token::Comma,
-            Token::Ident(String::from("y")),
+            Token::Ident("y".into()),
             Token::RParen,
             Token::LBrace,
-            Token::Ident(String::from("x")),
+            Token::Ident("x".into()),
             Token::Plus,
-            Token::Ident(String::from("y")),
+            Token::Ident("y".into()),
             Token::Semicolon,
             Token::RBrace,
             Token::Semicolon,
             Token::Let,
-            Token::Ident(String::from("result")),
+            Token::Ident("result".into()),
             Token::Assign,
-            Token::Ident(String::from("add")),
+            Token::Ident("add".into()),
             Token::LParen,
-            Token::Ident(String::from("five")),
+            Token::Ident("five".into()),
             Token::Comma,
-            Token::Ident(String::from("ten")),
+            Token::Ident("ten".into()),
             Token::RParen,
             Token::Semicolon,
             Token::Bang,
             Token::Minus,
             Token::Slash,
             Token::Asterisk,
-            Token::Int(String::from("5")),
+            Token::Int("5".into()),
             Token::Semicolon,
-            Token::Int(String::from("5")),
+            Token::Int("5".into()),
             Token::LessThan,
-            Token::Int(String::from("10")),
+            Token::Int("10".into()),
             Token::GreaterThan,
-            Token::Int(String::from("5")),
+            Token::Int("5".into()),
             Token::Semicolon,
             Token::If,
             Token::LParen,
-            Token::Int(String::from("5")),
+            Token::Int("5".into()),
             Token::LessThan,
-            Token::Int(String::from("10")),
+            Token::Int("10".into()),
             Token::RParen,
             Token::LBrace,
             Token::Return,
@@ -187,13 +503,13 @@ mod tests {
             Token::False,
             Token::Semicolon,
             Token::RBrace,
-            Token::Int(String::from("10")),
+            Token::Int("10".into()),
             Token::Equal,
-            Token::Int(String::from("10")),
+            Token::Int("10".into()),
             Token::Semicolon,
-            Token::Int(String::from("10")),
+            Token::Int("10".into()),
             Token::NotEqual,
-            Token::Int(String::from("9")),
+            Token::Int("9".into()),
             Token::Semicolon,
         ]
         .into_boxed_slice();
@@ -223,4 +539,232 @@ if (5 < 10) {
         let token = lexer.next_token();
         assert_eq!(token, None);
     }
+
+    #[test]
+    fn tracks_spans() {
+        let mut lexer = Lexer::new("let x = 10;\n  x == 5");
+        let expected = [
+            (Token::Let, 0, 3, 1, 1),
+            (Token::Ident("x".into()), 4, 5, 1, 5),
+            (Token::Assign, 6, 7, 1, 7),
+            (Token::Int("10".into()), 8, 10, 1, 9),
+            (Token::Semicolon, 10, 11, 1, 11),
+            (Token::Ident("x".into()), 14, 15, 2, 3),
+            (Token::Equal, 16, 18, 2, 5),
+            (Token::Int("5".into()), 19, 20, 2, 8),
+            (Token::Eof, 20, 20, 2, 9),
+        ];
+        for (token, start, end, line, column) in expected {
+            let span = Span {
+                start,
+                end,
+                line,
+                column,
+            };
+            assert_eq!(lexer.next_spanned_token(), SpannedToken { token, span });
+        }
+    }
+
+    #[test]
+    fn string_literals() {
+        let mut lexer = Lexer::new(r#""foo bar" "a\nb\t\"c\"\\" "\u{48}\u{1F600}" """#);
+        assert_eq!(lexer.next_token(), Some(Token::Str("foo bar".into())));
+        assert_eq!(lexer.next_token(), Some(Token::Str("a\nb\t\"c\"\\".into())));
+        assert_eq!(lexer.next_token(), Some(Token::Str("H😀".into())));
+        assert_eq!(lexer.next_token(), Some(Token::Str("".into())));
+        assert_eq!(lexer.next_token(), None);
+    }
+
+    #[test]
+    fn printed_strings_lex_back() {
+        for text in ["a\"b\\c\n\td", "\r\0\u{7f}\u{1b}", "${x} $ $${", "é😀"] {
+            let printed = Token::Str(text.into()).to_string();
+            let mut lexer = Lexer::new(&printed);
+            assert_eq!(
+                lexer.next_token(),
+                Some(Token::Str(text.into())),
+                "{}",
+                printed
+            );
+            assert_eq!(lexer.next_token(), None);
+        }
+        assert_eq!(
+            Token::Str("\r\0${".into()).to_string(),
+            r#""\u{d}\u{0}\${""#
+        );
+    }
+
+    #[test]
+    fn string_errors() {
+        let mut lexer = Lexer::new("let s = \"abc\n  def");
+        lexer.next_token();
+        lexer.next_token();
+        lexer.next_token();
+        let token = lexer.next_spanned_token();
+        assert_eq!(token.token, Token::UnterminatedString);
+        assert_eq!(
+            token.span,
+            Span {
+                start: 8,
+                end: 9,
+                line: 1,
+                column: 9
+            }
+        );
+
+        let mut lexer = Lexer::new(r#""a\qb\u{110000}" 1"#);
+        let token = lexer.next_spanned_token();
+        assert_eq!(token.token, Token::InvalidEscape("\\q".into()));
+        assert_eq!((token.span.start, token.span.end), (2, 4));
+        assert_eq!(lexer.next_token(), Some(Token::Int("1".into())));
+
+        let mut lexer = Lexer::new(r#""\é" "\q\éx\n""#);
+        let token = lexer.next_spanned_token();
+        assert_eq!(token.token, Token::InvalidEscape("\\é".into()));
+        assert_eq!((token.span.start, token.span.end), (1, 4));
+        assert_eq!(lexer.next_token(), Some(Token::InvalidEscape("\\q".into())));
+        assert_eq!(lexer.next_token(), None);
+    }
+
+    #[test]
+    fn interpolated_strings() {
+        let mut lexer = Lexer::new(r#"x = "a${b}c ${ f("}", {}) }\${d}""#);
+        lexer.next_token();
+        lexer.next_token();
+        let token = lexer.next_token();
+        let code = |source: &'static str, start: usize| StringPart::Code {
+            source: source.into(),
+            span: Span {
+                start,
+                end: start + source.len(),
+                line: 1,
+                column: start + 1,
+            },
+        };
+        assert_eq!(
+            token,
+            Some(Token::Interpolated(vec![
+                StringPart::Literal("a".into()),
+                code("b", 8),
+                StringPart::Literal("c ".into()),
+                code(r#" f("}", {}) "#, 14),
+                StringPart::Literal("${d}".into()),
+            ]))
+        );
+        assert_eq!(lexer.next_token(), None);
+
+        let mut lexer = Lexer::new(r#""${ "${1}" }" "${oops"#);
+        assert!(matches!(lexer.next_token(), Some(Token::Interpolated(_))));
+        assert_eq!(lexer.next_token(), Some(Token::UnterminatedString));
+    }
+
+    #[test]
+    fn lexes_at_an_origin() {
+        let origin = Span {
+            start: 10,
+            end: 10,
+            line: 3,
+            column: 5,
+        };
+        let mut lexer = Lexer::new_at("a +\n b", origin);
+        let spans: Vec<Span> = (0..3).map(|_| lexer.next_spanned_token().span).collect();
+        assert_eq!(
+            spans,
+            vec![
+                Span {
+                    start: 10,
+                    end: 11,
+                    line: 3,
+                    column: 5
+                },
+                Span {
+                    start: 12,
+                    end: 13,
+                    line: 3,
+                    column: 7
+                },
+                Span {
+                    start: 15,
+                    end: 16,
+                    line: 4,
+                    column: 2
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn brackets_and_colons() {
+        let mut lexer = Lexer::new("{a: [1, a][0]}");
+        let expected = [
+            Token::LBrace,
+            Token::Ident("a".into()),
+            Token::Colon,
+            Token::LBracket,
+            Token::Int("1".into()),
+            Token::Comma,
+            Token::Ident("a".into()),
+            Token::RBracket,
+            Token::LBracket,
+            Token::Int("0".into()),
+            Token::RBracket,
+            Token::RBrace,
+        ];
+        for token in expected {
+            assert_eq!(lexer.next_token(), Some(token));
+        }
+        assert_eq!(lexer.next_token(), None);
+    }
+
+    #[test]
+    fn borrows_from_the_input() {
+        let input = r#"name 42 "plain" "a\tb" "x${y}" @"#;
+        let mut lexer = Lexer::new(input);
+        let borrowed = |token: Option<Token>| match token {
+            Some(
+                Token::Ident(text) | Token::Int(text) | Token::Str(text) | Token::Illegal(text),
+            ) => {
+                matches!(text, Cow::Borrowed(_))
+            }
+            Some(Token::Interpolated(parts)) => parts.iter().all(|part| {
+                matches!(
+                    part,
+                    StringPart::Literal(Cow::Borrowed(_))
+                        | StringPart::Code {
+                            source: Cow::Borrowed(_),
+                            ..
+                        }
+                )
+            }),
+            _ => false,
+        };
+        assert!(borrowed(lexer.next_token()));
+        assert!(borrowed(lexer.next_token()));
+        assert!(borrowed(lexer.next_token()));
+        assert_eq!(
+            lexer.next_token(),
+            Some(Token::Str(Cow::Owned(String::from("a\tb"))))
+        );
+        assert!(borrowed(lexer.next_token()));
+        assert!(borrowed(lexer.next_token()));
+
+        let owned = Lexer::new(input).next_token().map(Token::into_owned);
+        assert_eq!(owned, Some(Token::Ident("name".into())));
+    }
+
+    #[test]
+    fn iterates_up_to_the_end_of_input() {
+        let mut tokens = Lexer::new("a + 1").peekable();
+        assert_eq!(tokens.peek().map(|t| t.span.start), Some(0));
+        let tokens: Vec<Token> = tokens.map(|t| t.token).collect();
+        assert_eq!(
+            tokens,
+            vec![
+                Token::Ident("a".into()),
+                Token::Plus,
+                Token::Int("1".into())
+            ]
+        );
+        assert_eq!(Lexer::new("  ").next(), None);
+    }
 }
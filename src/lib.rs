@@ -1,3 +1,15 @@
 pub mod ast;
+pub mod builtins;
+pub mod code;
+pub mod compiler;
+pub mod diagnostics;
+pub mod disasm;
+pub mod eval;
+pub mod interpreter;
 pub mod lexer;
+pub mod mkc;
+pub mod repl;
 pub mod token;
+pub mod vm;
+
+pub use interpreter::{ConversionError, Error, Interpreter};
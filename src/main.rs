@@ -1,13 +1,302 @@
-// mod ast;
-mod lexer;
-mod repl;
-mod token;
-
-fn main() {
-    println!(
-        "Hello {}! This is the Monkey programming language!",
-        whoami::username()
-    );
-    println!("Feel free to type in commands");
-    repl::start();
+use interpreter::ast::{ParseError, Parser};
+use interpreter::builtins::Builtins;
+use interpreter::compiler::{self, Bytecode, CompileError};
+use interpreter::diagnostics::{Diagnostic, Mode};
+use interpreter::disasm::Listing;
+use interpreter::eval::{EvalError, Object};
+use interpreter::mkc::{self, Executable};
+use interpreter::{repl, vm, Error, Interpreter};
+use std::io::{self, Read, Write};
+use std::path::Path;
+use std::process::ExitCode;
+
+const USAGE: &str = "Usage:
+    interpreter [repl]                  Start an interactive session
+    interpreter [OPTIONS] run <FILE>    Run a Monkey source or compiled file, or stdin if FILE is `-`
+    interpreter [OPTIONS] -e <SOURCE>   Evaluate SOURCE and print its value
+    interpreter compile <FILE> [-o OUT] Compile FILE to bytecode in OUT, by default FILE.mkc
+    interpreter disasm <FILE>           Print the bytecode of a Monkey source or compiled file
+    interpreter -h, --help              Print this message
+
+Options:
+    --engine <eval|vm>   Walk the syntax tree (the default), or compile to bytecode and run it
+                         on the virtual machine";
+
+// Exit codes follow the BSD sysexits convention.
+const EXIT_USAGE: u8 = 64;
+const EXIT_PARSE_ERROR: u8 = 65;
+const EXIT_NO_INPUT: u8 = 66;
+const EXIT_RUNTIME_ERROR: u8 = 70;
+const EXIT_CANNOT_CREATE: u8 = 73;
+
+/// How programs are executed.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+enum Engine {
+    Eval,
+    Vm,
+}
+
+/// The stack of the thread running programs. The evaluator recurses on the native stack, and
+/// debug builds need more than the main thread has to reach `eval::MAX_CALL_DEPTH`.
+const STACK_SIZE: usize = 256 << 20;
+
+/// How much of `STACK_SIZE` evaluation may use, leaving the rest to parsing and printing.
+const STACK_LIMIT: usize = STACK_SIZE / 2;
+
+fn main() -> ExitCode {
+    let thread = std::thread::Builder::new().stack_size(STACK_SIZE);
+    match thread.spawn(run_command).map(|handle| handle.join()) {
+        Ok(Ok(code)) => code,
+        Ok(Err(_)) => ExitCode::FAILURE,
+        Err(error) => {
+            eprintln!("error: could not start the interpreter: {}", error);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+fn run_command() -> ExitCode {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let mut args: Vec<&str> = args.iter().map(String::as_str).collect();
+    let engine = match take_engine(&mut args) {
+        Ok(engine) => engine,
+        Err(message) => {
+            eprintln!("error: {}\n\n{}", message, USAGE);
+            return ExitCode::from(EXIT_USAGE);
+        }
+    };
+    match (args.as_slice(), engine) {
+        ([] | ["repl"], None) => {
+            println!(
+                "Hello {}! This is the Monkey programming language!",
+                whoami::username()
+            );
+            println!("Feel free to type in commands, or :help for a list of REPL commands");
+            repl::start(STACK_LIMIT);
+            ExitCode::SUCCESS
+        }
+        (["run", path], engine) => match read_input(path) {
+            Ok(Input::Source(source)) => {
+                execute(&source, path, false, engine.unwrap_or(Engine::Eval))
+            }
+            Ok(Input::Compiled(bytes)) if engine != Some(Engine::Eval) => {
+                run_compiled(&bytes, path)
+            }
+            Ok(Input::Compiled(_)) => {
+                eprintln!(
+                    "error: {} is compiled and can only be run with `--engine vm`",
+                    path
+                );
+                ExitCode::from(EXIT_USAGE)
+            }
+            Err(error) => {
+                eprintln!("error: could not read {}: {}", path, error);
+                ExitCode::from(EXIT_NO_INPUT)
+            }
+        },
+        (["compile", path], None) => {
+            let output = Path::new(path).with_extension("mkc");
+            compile_file(path, &output)
+        }
+        (["compile", path, "-o", output], None) => compile_file(path, Path::new(output)),
+        (["disasm", path], None) => disassemble(path),
+        (["-e", source], engine) => execute(source, "<expr>", true, engine.unwrap_or(Engine::Eval)),
+        (["-h" | "--help"], None) => {
+            println!("{}", USAGE);
+            ExitCode::SUCCESS
+        }
+        _ => {
+            eprintln!("{}", USAGE);
+            ExitCode::from(EXIT_USAGE)
+        }
+    }
+}
+
+/// Removes `--engine NAME` from `args`, returning the engine it selects if it was given.
+fn take_engine(args: &mut Vec<&str>) -> Result<Option<Engine>, String> {
+    let Some(position) = args.iter().position(|&arg| arg == "--engine") else {
+        return Ok(None);
+    };
+    let engine = match args.get(position + 1) {
+        Some(&"eval") => Engine::Eval,
+        Some(&"vm") => Engine::Vm,
+        Some(name) => {
+            return Err(format!(
+                "unknown engine `{}`, expected `eval` or `vm`",
+                name
+            ))
+        }
+        None => return Err("`--engine` needs a value".to_string()),
+    };
+    args.drain(position..position + 2);
+    Ok(Some(engine))
+}
+
+/// The contents of a file passed on the command line.
+enum Input {
+    Source(String),
+    /// A program written by `interpreter compile`.
+    Compiled(Vec<u8>),
+}
+
+/// Reads the file at `path`, or all of stdin if `path` is `-`.
+fn read_input(path: &str) -> io::Result<Input> {
+    let bytes = if path == "-" {
+        let mut bytes = Vec::new();
+        io::stdin().read_to_end(&mut bytes)?;
+        bytes
+    } else {
+        std::fs::read(path)?
+    };
+    if mkc::is_compiled(&bytes) {
+        return Ok(Input::Compiled(bytes));
+    }
+    String::from_utf8(bytes)
+        .map(Input::Source)
+        .map_err(|error| io::Error::new(io::ErrorKind::InvalidData, error))
+}
+
+/// Parses and runs `source` with `engine`, reporting errors as diagnostics against `origin`.
+fn execute(source: &str, origin: &str, print_result: bool, engine: Engine) -> ExitCode {
+    let result = match engine {
+        Engine::Eval => Interpreter::new()
+            .with_stack_limit(STACK_LIMIT)
+            .eval_str(source)
+            .map_err(Failure::from),
+        Engine::Vm => {
+            compile(source).and_then(|bytecode| Ok(vm::run(&bytecode, &Builtins::standard())?))
+        }
+    };
+    match result {
+        Ok(Object::Null) => ExitCode::SUCCESS,
+        Ok(value) => {
+            if print_result {
+                println!("{}", value);
+            }
+            ExitCode::SUCCESS
+        }
+        Err(error) => report(&error, source, origin),
+    }
+}
+
+/// Runs a program written by `interpreter compile`; its errors point into the embedded source.
+fn run_compiled(bytes: &[u8], path: &str) -> ExitCode {
+    let executable = match mkc::decode(bytes) {
+        Ok(executable) => executable,
+        Err(error) => {
+            eprintln!("error: could not load {}: {}", path, error);
+            return ExitCode::from(EXIT_PARSE_ERROR);
+        }
+    };
+    match vm::run(&executable.bytecode, &Builtins::standard()) {
+        Ok(_) => ExitCode::SUCCESS,
+        Err(error) => report(&Failure::Eval(error), &executable.source, path),
+    }
+}
+
+fn compile_file(path: &str, output: &Path) -> ExitCode {
+    let source = match read_input(path) {
+        Ok(Input::Source(source)) => source,
+        Ok(Input::Compiled(_)) => {
+            eprintln!("error: {} is already compiled", path);
+            return ExitCode::from(EXIT_USAGE);
+        }
+        Err(error) => {
+            eprintln!("error: could not read {}: {}", path, error);
+            return ExitCode::from(EXIT_NO_INPUT);
+        }
+    };
+    let bytecode = match compile(&source) {
+        Ok(bytecode) => bytecode,
+        Err(error) => return report(&error, &source, path),
+    };
+    let bytes = mkc::encode(&Executable { source, bytecode });
+    match std::fs::write(output, bytes) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(error) => {
+            eprintln!("error: could not write {}: {}", output.display(), error);
+            ExitCode::from(EXIT_CANNOT_CREATE)
+        }
+    }
+}
+
+fn disassemble(path: &str) -> ExitCode {
+    let executable = match read_input(path) {
+        Ok(Input::Source(source)) => match compile(&source) {
+            Ok(bytecode) => Executable { source, bytecode },
+            Err(error) => return report(&error, &source, path),
+        },
+        Ok(Input::Compiled(bytes)) => match mkc::decode(&bytes) {
+            Ok(executable) => executable,
+            Err(error) => {
+                eprintln!("error: could not load {}: {}", path, error);
+                return ExitCode::from(EXIT_PARSE_ERROR);
+            }
+        },
+        Err(error) => {
+            eprintln!("error: could not read {}: {}", path, error);
+            return ExitCode::from(EXIT_NO_INPUT);
+        }
+    };
+    let listing = Listing {
+        bytecode: &executable.bytecode,
+        source: &executable.source,
+    };
+    // Listings are often piped into a pager, which may close before reading all of it.
+    let _ = write!(io::stdout().lock(), "{}", listing);
+    ExitCode::SUCCESS
+}
+
+/// Why a program given on the command line failed to run.
+enum Failure {
+    Parse(Vec<ParseError>),
+    Compile(CompileError),
+    Eval(EvalError),
+    Other(Error),
+}
+
+impl From<Error> for Failure {
+    fn from(error: Error) -> Self {
+        match error {
+            Error::Parse(errors) => Failure::Parse(errors),
+            Error::Eval(error) => Failure::Eval(error),
+            error => Failure::Other(error),
+        }
+    }
+}
+
+impl From<EvalError> for Failure {
+    fn from(error: EvalError) -> Self {
+        Failure::Eval(error)
+    }
+}
+
+fn compile(source: &str) -> Result<Bytecode, Failure> {
+    let program = Parser::new(source).parse().map_err(Failure::Parse)?;
+    compiler::compile(&program).map_err(Failure::Compile)
+}
+
+/// Renders `error` as diagnostics against `source` and returns the matching exit code.
+fn report(failure: &Failure, source: &str, origin: &str) -> ExitCode {
+    let mode = Mode::for_stderr();
+    match failure {
+        Failure::Parse(errors) => {
+            for error in errors {
+                eprint!("{}", Diagnostic::from(error).render(source, origin, mode));
+            }
+            ExitCode::from(EXIT_PARSE_ERROR)
+        }
+        Failure::Compile(error) => {
+            eprint!("{}", Diagnostic::from(error).render(source, origin, mode));
+            ExitCode::from(EXIT_PARSE_ERROR)
+        }
+        Failure::Eval(error) => {
+            eprint!("{}", Diagnostic::from(error).render(source, origin, mode));
+            ExitCode::from(EXIT_RUNTIME_ERROR)
+        }
+        Failure::Other(error) => {
+            eprintln!("error: {}", error);
+            ExitCode::from(EXIT_RUNTIME_ERROR)
+        }
+    }
 }
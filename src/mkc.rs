@@ -0,0 +1,814 @@
+use crate::code::{self, CompiledFunction, Fallback, Opcode};
+use crate::compiler::Bytecode;
+use crate::eval::Object;
+use crate::token::Span;
+use std::collections::HashMap;
+use std::fmt;
+use std::rc::Rc;
+
+pub const MAGIC: &[u8; 4] = b"MKC\0";
+
+/// Bumped whenever the layout of the file or the meaning of an opcode changes.
+pub const FORMAT_VERSION: u16 = 4;
+
+const HEADER_LEN: usize = MAGIC.len() + 2 + 4;
+
+const TAG_INTEGER: u8 = 0;
+const TAG_STRING: u8 = 1;
+const TAG_FUNCTION: u8 = 2;
+
+const TAG_OUTER: u8 = 0;
+const TAG_GLOBAL: u8 = 1;
+
+/// A compiled program together with the source it was compiled from.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct Executable {
+    pub source: String,
+    pub bytecode: Bytecode,
+}
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum DecodeError {
+    NotCompiled,
+    UnsupportedVersion(u16),
+    ChecksumMismatch,
+    UnexpectedEnd,
+    Invalid(String),
+}
+
+impl fmt::Display for DecodeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DecodeError::NotCompiled => write!(f, "not a compiled Monkey program"),
+            DecodeError::UnsupportedVersion(version) => write!(
+                f,
+                "compiled with format version {}, but this interpreter only runs version {}; \
+                 compile it again from its source",
+                version, FORMAT_VERSION
+            ),
+            DecodeError::ChecksumMismatch => write!(f, "checksum mismatch, the file is corrupt"),
+            DecodeError::UnexpectedEnd => {
+                write!(f, "unexpected end of file, the file is truncated")
+            }
+            DecodeError::Invalid(reason) => write!(f, "invalid bytecode: {}", reason),
+        }
+    }
+}
+
+impl std::error::Error for DecodeError {}
+
+/// Returns whether `bytes` look like a compiled program rather than source code.
+pub fn is_compiled(bytes: &[u8]) -> bool {
+    bytes.starts_with(MAGIC)
+}
+
+/// The CRC-32 (IEEE) checksum of `bytes`.
+fn crc32(bytes: &[u8]) -> u32 {
+    let mut crc = !0u32;
+    for &byte in bytes {
+        crc ^= byte as u32;
+        for _ in 0..8 {
+            let mask = (crc & 1).wrapping_neg();
+            crc = (crc >> 1) ^ (0xEDB8_8320 & mask);
+        }
+    }
+    !crc
+}
+
+/// Encodes `executable` in the `.mkc` format.
+///
+/// A file starts with a header of the magic bytes `MKC\0`, the format version as a `u16` and a
+/// CRC-32 checksum of the rest of the file as a `u32`. The body holds the program's source, for
+/// error messages, then its global names, constant pool and top-level function. Integers are
+/// little-endian; strings and lists are prefixed with their length as a `u32`.
+pub fn encode(executable: &Executable) -> Vec<u8> {
+    let mut body = Encoder::default();
+    body.string(&executable.source);
+    let Bytecode {
+        main,
+        constants,
+        globals,
+    } = &executable.bytecode;
+    body.u32(globals.len());
+    for name in globals {
+        body.string(name);
+    }
+    body.u32(constants.len());
+    for constant in constants {
+        match constant {
+            Object::Integer(value) => {
+                body.u8(TAG_INTEGER);
+                body.bytes.extend_from_slice(&value.to_le_bytes());
+            }
+            Object::String(value) => {
+                body.u8(TAG_STRING);
+                body.string(value);
+            }
+            Object::CompiledFunction(function) => {
+                body.u8(TAG_FUNCTION);
+                body.function(function);
+            }
+            other => unreachable!("the compiler doesn't emit {} constants", other.type_name()),
+        }
+    }
+    body.function(main);
+
+    let mut bytes = Vec::with_capacity(HEADER_LEN + body.bytes.len());
+    bytes.extend_from_slice(MAGIC);
+    bytes.extend_from_slice(&FORMAT_VERSION.to_le_bytes());
+    bytes.extend_from_slice(&crc32(&body.bytes).to_le_bytes());
+    bytes.extend(body.bytes);
+    bytes
+}
+
+/// Reads a compiled program, checking that the VM can run its instructions safely.
+pub fn decode(bytes: &[u8]) -> Result<Executable, DecodeError> {
+    if !is_compiled(bytes) {
+        return Err(DecodeError::NotCompiled);
+    }
+    let mut header = Decoder::new(&bytes[MAGIC.len()..]);
+    let version = u16::from_le_bytes(header.array()?);
+    if version != FORMAT_VERSION {
+        return Err(DecodeError::UnsupportedVersion(version));
+    }
+    let checksum = u32::from_le_bytes(header.array()?);
+    let body = &bytes[HEADER_LEN..];
+    if crc32(body) != checksum {
+        return Err(DecodeError::ChecksumMismatch);
+    }
+
+    let mut body = Decoder::new(body);
+    let source = body.string()?;
+    let globals = (0..body.u32()?)
+        .map(|_| body.string())
+        .collect::<Result<Vec<_>, _>>()?;
+    let mut constants = Vec::new();
+    for _ in 0..body.u32()? {
+        let constant = match body.u8()? {
+            TAG_INTEGER => Object::Integer(i64::from_le_bytes(body.array()?)),
+            TAG_STRING => Object::String(body.string()?),
+            TAG_FUNCTION => Object::CompiledFunction(Rc::new(body.function()?)),
+            tag => {
+                return Err(DecodeError::Invalid(format!(
+                    "unknown constant tag {}",
+                    tag
+                )))
+            }
+        };
+        constants.push(constant);
+    }
+    let main = body.function()?;
+    if body.position != body.bytes.len() {
+        return Err(DecodeError::Invalid("trailing bytes".to_string()));
+    }
+
+    let bytecode = Bytecode {
+        main,
+        constants,
+        globals,
+    };
+    verify(&bytecode)?;
+    Ok(Executable { source, bytecode })
+}
+
+/// The most local slots a function can have, as many as `OpGetLocal` can address.
+const MAX_LOCALS: usize = u8::MAX as usize + 1;
+
+/// A decoded instruction of a function being verified.
+struct Instruction {
+    offset: usize,
+    opcode: Opcode,
+    operands: Vec<usize>,
+}
+
+/// Names a function in error messages: the top level, or the index of its constant.
+fn function_name(index: Option<usize>) -> String {
+    match index {
+        None => "the top level".to_string(),
+        Some(index) => format!("constant {}", index),
+    }
+}
+
+fn invalid(function: Option<usize>, offset: usize, reason: &str) -> DecodeError {
+    DecodeError::Invalid(format!(
+        "{} at offset {} of {}",
+        reason,
+        offset,
+        function_name(function)
+    ))
+}
+
+/// Checks the functions of `bytecode` so that the VM can run them without going out of bounds:
+/// their instructions only refer to constants, globals, locals and offsets that exist, and never
+/// pop more values than the stack holds.
+fn verify(bytecode: &Bytecode) -> Result<(), DecodeError> {
+    let mut functions = vec![(None, &bytecode.main)];
+    for (index, constant) in bytecode.constants.iter().enumerate() {
+        if let Object::CompiledFunction(function) = constant {
+            functions.push((Some(index), function.as_ref()));
+        }
+    }
+    let mut decoded = Vec::with_capacity(functions.len());
+    // The function each function constant is created in, as its closures refer to the locals of
+    // that function's call.
+    let mut creators = HashMap::new();
+    for &(index, function) in &functions {
+        let instructions = decode_instructions(index, function)?;
+        for instruction in &instructions {
+            if instruction.opcode == Opcode::Closure
+                && creators.insert(instruction.operands[0], index).is_some()
+            {
+                return Err(invalid(
+                    index,
+                    instruction.offset,
+                    "function created in more than one place",
+                ));
+            }
+        }
+        decoded.push(instructions);
+    }
+    // Returns the function whose locals `OpGetOuter` refers to with `depth` in the function
+    // constant `index`, if there is one. Closures created at the top level have no enclosing
+    // locals.
+    let enclosing = |mut index: Option<usize>, depth: usize| {
+        for _ in 0..depth {
+            index = *creators.get(&index?)?;
+        }
+        match bytecode.constants.get(index?) {
+            Some(Object::CompiledFunction(function)) => Some(function.as_ref()),
+            _ => None,
+        }
+    };
+
+    for (&(index, function), instructions) in functions.iter().zip(&decoded) {
+        let name = function_name(index);
+        if index.is_none() && !(function.locals.is_empty() && function.num_parameters == 0) {
+            return Err(DecodeError::Invalid(format!("{} has locals", name)));
+        }
+        if function.locals.len() > MAX_LOCALS {
+            return Err(DecodeError::Invalid(format!(
+                "{} has more than {} locals",
+                name, MAX_LOCALS
+            )));
+        }
+        if function.num_parameters > function.locals.len() {
+            return Err(DecodeError::Invalid(format!(
+                "{} has more parameters than locals",
+                name
+            )));
+        }
+        if function.fallbacks.len() != function.locals.len() - function.num_parameters {
+            return Err(DecodeError::Invalid(format!(
+                "{} doesn't have a fallback for each of its `let` locals",
+                name
+            )));
+        }
+        for fallback in &function.fallbacks {
+            let in_range = match *fallback {
+                // Falling back to the function's own slots would never end.
+                Fallback::Outer { depth, index: slot } => {
+                    depth > 0
+                        && enclosing(index, depth).is_some_and(|outer| slot < outer.locals.len())
+                }
+                Fallback::Global(global) => global < bytecode.globals.len(),
+            };
+            if !in_range {
+                return Err(DecodeError::Invalid(format!(
+                    "{} has a fallback out of range",
+                    name
+                )));
+            }
+        }
+        for instruction in instructions {
+            let &Instruction {
+                offset,
+                opcode,
+                ref operands,
+            } = instruction;
+            match opcode {
+                Opcode::Constant if operands[0] >= bytecode.constants.len() => {
+                    return Err(invalid(index, offset, "constant out of range"))
+                }
+                Opcode::Closure => match bytecode.constants.get(operands[0]) {
+                    Some(Object::CompiledFunction(_)) => {}
+                    _ => {
+                        return Err(invalid(
+                            index,
+                            offset,
+                            "closure of a constant that isn't a function",
+                        ))
+                    }
+                },
+                Opcode::GetGlobal | Opcode::SetGlobal if operands[0] >= bytecode.globals.len() => {
+                    return Err(invalid(index, offset, "global out of range"))
+                }
+                Opcode::GetLocal | Opcode::SetLocal if operands[0] >= function.locals.len() => {
+                    return Err(invalid(index, offset, "local out of range"))
+                }
+                Opcode::GetOuter => {
+                    let outer = enclosing(index, operands[0]);
+                    if outer.is_none_or(|outer| operands[1] >= outer.locals.len()) {
+                        return Err(invalid(index, offset, "outer local out of range"));
+                    }
+                }
+                Opcode::Jump | Opcode::JumpNotTruthy => {
+                    let target = operands[0];
+                    if target >= function.instructions.len() {
+                        return Err(invalid(index, offset, "jump out of range"));
+                    }
+                    if instructions
+                        .binary_search_by_key(&target, |instruction| instruction.offset)
+                        .is_err()
+                    {
+                        return Err(invalid(
+                            index,
+                            offset,
+                            "jump into the middle of an instruction",
+                        ));
+                    }
+                }
+                _ => {}
+            }
+        }
+        verify_stack(index, instructions)?;
+    }
+    Ok(())
+}
+
+/// Splits the instructions of `function` up, checking that they are complete and that the last
+/// one returns, so that the VM can't run past the end.
+fn decode_instructions(
+    index: Option<usize>,
+    function: &CompiledFunction,
+) -> Result<Vec<Instruction>, DecodeError> {
+    let bytes = &function.instructions;
+    let mut instructions = Vec::new();
+    let mut offset = 0;
+    while offset < bytes.len() {
+        let Ok(opcode) = Opcode::try_from(bytes[offset]) else {
+            return Err(invalid(index, offset, "unknown opcode"));
+        };
+        let width: usize = opcode.operand_widths().iter().sum();
+        if offset + 1 + width > bytes.len() {
+            return Err(invalid(index, offset, "truncated instruction"));
+        }
+        let (operands, _) = code::read_operands(opcode, &bytes[offset + 1..]);
+        instructions.push(Instruction {
+            offset,
+            opcode,
+            operands,
+        });
+        offset += 1 + width;
+    }
+    match instructions.last() {
+        Some(instruction) if instruction.opcode == Opcode::ReturnValue => Ok(instructions),
+        _ => Err(invalid(index, bytes.len(), "missing return")),
+    }
+}
+
+/// Returns how many values `instruction` pops off the stack, and how many it pushes.
+fn stack_effect(instruction: &Instruction) -> (usize, usize) {
+    let count = instruction.operands.first().copied().unwrap_or_default();
+    match instruction.opcode {
+        Opcode::Constant
+        | Opcode::True
+        | Opcode::False
+        | Opcode::Null
+        | Opcode::GetGlobal
+        | Opcode::GetLocal
+        | Opcode::GetOuter
+        | Opcode::Closure => (0, 1),
+        Opcode::Jump => (0, 0),
+        Opcode::Pop
+        | Opcode::JumpNotTruthy
+        | Opcode::SetGlobal
+        | Opcode::SetLocal
+        | Opcode::ReturnValue => (1, 0),
+        Opcode::Minus | Opcode::Bang | Opcode::HashKey => (1, 1),
+        Opcode::Add
+        | Opcode::Sub
+        | Opcode::Mul
+        | Opcode::Div
+        | Opcode::Equal
+        | Opcode::NotEqual
+        | Opcode::LessThan
+        | Opcode::GreaterThan
+        | Opcode::Index => (2, 1),
+        Opcode::Array | Opcode::Interpolate => (count, 1),
+        Opcode::Hash => (2 * count, 1),
+        Opcode::Call => (count + 1, 1),
+    }
+}
+
+/// Follows every path through a function's instructions to check that none of them pops more
+/// values than the stack holds. Paths that meet must leave the stack equally deep, which also
+/// keeps loops from growing it.
+fn verify_stack(index: Option<usize>, instructions: &[Instruction]) -> Result<(), DecodeError> {
+    let position = |offset: usize| {
+        instructions
+            .binary_search_by_key(&offset, |instruction| instruction.offset)
+            .expect("jump targets were checked to be instructions")
+    };
+    let mut depths = vec![None; instructions.len()];
+    let mut pending: Vec<(usize, usize)> = vec![(0, 0)];
+    while let Some((i, depth)) = pending.pop() {
+        let instruction = &instructions[i];
+        match depths[i] {
+            Some(known) if known == depth => continue,
+            Some(_) => {
+                return Err(invalid(
+                    index,
+                    instruction.offset,
+                    "inconsistent stack depth",
+                ))
+            }
+            None => depths[i] = Some(depth),
+        }
+        let (pops, pushes) = stack_effect(instruction);
+        let Some(rest) = depth.checked_sub(pops) else {
+            return Err(invalid(index, instruction.offset, "stack underflow"));
+        };
+        let depth = rest + pushes;
+        // The last instruction returns, so every other one has a next instruction.
+        match instruction.opcode {
+            Opcode::ReturnValue => {}
+            Opcode::Jump => pending.push((position(instruction.operands[0]), depth)),
+            Opcode::JumpNotTruthy => {
+                pending.push((i + 1, depth));
+                pending.push((position(instruction.operands[0]), depth));
+            }
+            _ => pending.push((i + 1, depth)),
+        }
+    }
+    Ok(())
+}
+
+#[derive(Default)]
+struct Encoder {
+    bytes: Vec<u8>,
+}
+
+impl Encoder {
+    fn u8(&mut self, value: u8) {
+        self.bytes.push(value);
+    }
+
+    fn u32(&mut self, len: usize) {
+        self.bytes.extend_from_slice(&(len as u32).to_le_bytes());
+    }
+
+    fn string(&mut self, value: &str) {
+        self.u32(value.len());
+        self.bytes.extend_from_slice(value.as_bytes());
+    }
+
+    fn function(&mut self, function: &CompiledFunction) {
+        self.u32(function.instructions.len());
+        self.bytes.extend_from_slice(&function.instructions);
+        self.u32(function.locals.len());
+        for name in &function.locals {
+            self.string(name);
+        }
+        self.u32(function.num_parameters);
+        self.u32(function.fallbacks.len());
+        for fallback in &function.fallbacks {
+            match *fallback {
+                Fallback::Outer { depth, index } => {
+                    self.u8(TAG_OUTER);
+                    self.u32(depth);
+                    self.u32(index);
+                }
+                Fallback::Global(index) => {
+                    self.u8(TAG_GLOBAL);
+                    self.u32(index);
+                }
+            }
+        }
+        self.u32(function.spans.len());
+        for (offset, span) in &function.spans {
+            for value in [*offset, span.start, span.end, span.line, span.column] {
+                self.u32(value);
+            }
+        }
+        self.u32(function.lines.len());
+        for &(offset, line) in &function.lines {
+            self.u32(offset);
+            self.u32(line);
+        }
+        self.string(&function.source);
+    }
+}
+
+struct Decoder<'a> {
+    bytes: &'a [u8],
+    position: usize,
+}
+
+impl<'a> Decoder<'a> {
+    fn new(bytes: &'a [u8]) -> Self {
+        Self { bytes, position: 0 }
+    }
+
+    fn take(&mut self, len: usize) -> Result<&'a [u8], DecodeError> {
+        let end = self
+            .position
+            .checked_add(len)
+            .filter(|&end| end <= self.bytes.len())
+            .ok_or(DecodeError::UnexpectedEnd)?;
+        let bytes = &self.bytes[self.position..end];
+        self.position = end;
+        Ok(bytes)
+    }
+
+    fn array<const N: usize>(&mut self) -> Result<[u8; N], DecodeError> {
+        let mut array = [0; N];
+        array.copy_from_slice(self.take(N)?);
+        Ok(array)
+    }
+
+    fn u8(&mut self) -> Result<u8, DecodeError> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn u32(&mut self) -> Result<usize, DecodeError> {
+        Ok(u32::from_le_bytes(self.array()?) as usize)
+    }
+
+    fn string(&mut self) -> Result<String, DecodeError> {
+        let len = self.u32()?;
+        let bytes = self.take(len)?;
+        String::from_utf8(bytes.to_vec())
+            .map_err(|_| DecodeError::Invalid("string is not UTF-8".to_string()))
+    }
+
+    fn function(&mut self) -> Result<CompiledFunction, DecodeError> {
+        let len = self.u32()?;
+        let instructions = self.take(len)?.to_vec();
+        let locals = (0..self.u32()?)
+            .map(|_| self.string())
+            .collect::<Result<Vec<_>, _>>()?;
+        let num_parameters = self.u32()?;
+        let mut fallbacks = Vec::new();
+        for _ in 0..self.u32()? {
+            fallbacks.push(match self.u8()? {
+                TAG_OUTER => Fallback::Outer {
+                    depth: self.u32()?,
+                    index: self.u32()?,
+                },
+                TAG_GLOBAL => Fallback::Global(self.u32()?),
+                tag => {
+                    return Err(DecodeError::Invalid(format!(
+                        "unknown fallback tag {}",
+                        tag
+                    )))
+                }
+            });
+        }
+        let mut spans = Vec::new();
+        for _ in 0..self.u32()? {
+            let offset = self.u32()?;
+            let span = Span {
+                start: self.u32()?,
+                end: self.u32()?,
+                line: self.u32()?,
+                column: self.u32()?,
+            };
+            spans.push((offset, span));
+        }
+        let mut lines = Vec::new();
+        for _ in 0..self.u32()? {
+            lines.push((self.u32()?, self.u32()?));
+        }
+        let source = self.string()?;
+        Ok(CompiledFunction {
+            instructions,
+            locals,
+            num_parameters,
+            fallbacks,
+            spans,
+            lines,
+            source,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ast::Parser;
+    use crate::builtins::Builtins;
+    use crate::code::make;
+    use crate::compiler::compile;
+    use crate::vm;
+
+    fn executable(source: &str) -> Executable {
+        let program = Parser::new(source).parse().unwrap();
+        Executable {
+            source: source.to_string(),
+            bytecode: compile(&program).unwrap(),
+        }
+    }
+
+    #[test]
+    fn computes_crc32() {
+        assert_eq!(crc32(b""), 0);
+        assert_eq!(crc32(b"123456789"), 0xCBF4_3926);
+    }
+
+    #[test]
+    fn round_trips_programs() {
+        let executable = executable(
+            r#"let greet = fn(name) { "hi ${name}, " + str(-42) }; greet("ann") + str([true])"#,
+        );
+        let bytes = encode(&executable);
+        assert!(is_compiled(&bytes));
+        let decoded = decode(&bytes).unwrap();
+        assert_eq!(decoded, executable);
+        assert_eq!(
+            vm::run(&decoded.bytecode, &Builtins::standard()),
+            Ok(Object::String("hi ann, -42[true]".to_string()))
+        );
+    }
+
+    #[test]
+    fn accepts_what_the_compiler_emits() {
+        let nested = executable(
+            "let f = fn(x) { let g = fn(y) { if (y) { return x + 1; } {x: [y]}[x] }; 1 + g(x) }; \
+             f(1)",
+        );
+        let decoded = decode(&encode(&nested)).unwrap();
+        assert_eq!(
+            vm::run(&decoded.bytecode, &Builtins::standard()),
+            Ok(Object::Integer(3))
+        );
+    }
+
+    #[test]
+    fn rejects_invalid_files() {
+        let bytes = encode(&executable("let x = 1; x * 2"));
+        assert_eq!(decode(b"let x = 1;"), Err(DecodeError::NotCompiled));
+
+        let mut newer = bytes.clone();
+        newer[4..6].copy_from_slice(&(FORMAT_VERSION + 1).to_le_bytes());
+        let error = decode(&newer).unwrap_err();
+        assert_eq!(error, DecodeError::UnsupportedVersion(FORMAT_VERSION + 1));
+        assert!(error
+            .to_string()
+            .starts_with("compiled with format version 5"));
+
+        let mut corrupt = bytes.clone();
+        *corrupt.last_mut().unwrap() ^= 1;
+        assert_eq!(decode(&corrupt), Err(DecodeError::ChecksumMismatch));
+        assert_eq!(decode(&bytes[..5]), Err(DecodeError::UnexpectedEnd));
+
+        // Bytecode that passes the checksum is still checked before it is run.
+        let mut unverified = executable("let x = 1; x * 2");
+        unverified.bytecode.constants.clear();
+        assert_eq!(
+            decode(&encode(&unverified)),
+            Err(DecodeError::Invalid(
+                "constant out of range at offset 0 of the top level".to_string()
+            ))
+        );
+
+        let cases = [
+            (
+                vec![make(Opcode::Pop, &[]), make(Opcode::ReturnValue, &[])],
+                "stack underflow at offset 0 of the top level",
+            ),
+            (
+                vec![make(Opcode::Jump, &[4]), make(Opcode::ReturnValue, &[])],
+                "jump out of range at offset 0 of the top level",
+            ),
+            (
+                vec![
+                    make(Opcode::Jump, &[4]),
+                    make(Opcode::Constant, &[0]),
+                    make(Opcode::ReturnValue, &[]),
+                ],
+                "jump into the middle of an instruction at offset 0 of the top level",
+            ),
+            (
+                vec![
+                    make(Opcode::Constant, &[0]),
+                    make(Opcode::Call, &[2]),
+                    make(Opcode::ReturnValue, &[]),
+                ],
+                "stack underflow at offset 3 of the top level",
+            ),
+            (
+                vec![
+                    make(Opcode::Null, &[]),
+                    make(Opcode::True, &[]),
+                    make(Opcode::JumpNotTruthy, &[6]),
+                    make(Opcode::Null, &[]),
+                    make(Opcode::ReturnValue, &[]),
+                ],
+                "inconsistent stack depth at offset 6 of the top level",
+            ),
+            (
+                vec![make(Opcode::Null, &[]), make(Opcode::Jump, &[0])],
+                "missing return at offset 4 of the top level",
+            ),
+            (
+                vec![make(Opcode::GetLocal, &[0]), make(Opcode::ReturnValue, &[])],
+                "local out of range at offset 0 of the top level",
+            ),
+            (
+                vec![
+                    make(Opcode::GetOuter, &[1, 0]),
+                    make(Opcode::ReturnValue, &[]),
+                ],
+                "outer local out of range at offset 0 of the top level",
+            ),
+        ];
+        for (instructions, expected) in cases {
+            let mut unverified = executable("1");
+            unverified.bytecode.main.instructions = instructions.concat();
+            assert_eq!(
+                decode(&encode(&unverified)),
+                Err(DecodeError::Invalid(expected.to_string())),
+                "{}",
+                expected
+            );
+        }
+
+        // A function's frame must hold its parameters, and a function made at the top level has
+        // no enclosing locals.
+        let valid = executable("fn(x) { x + 1 }");
+        let Object::CompiledFunction(function) = &valid.bytecode.constants[1] else {
+            panic!("expected a function, got {:?}", valid.bytecode.constants[1]);
+        };
+        let returning = |instructions: [Vec<u8>; 2]| CompiledFunction {
+            instructions: instructions.concat(),
+            ..CompiledFunction::clone(function)
+        };
+        let cases = [
+            (
+                CompiledFunction {
+                    locals: Vec::new(),
+                    ..CompiledFunction::clone(function)
+                },
+                "constant 1 has more parameters than locals",
+            ),
+            (
+                CompiledFunction {
+                    locals: vec!["x".to_string(); MAX_LOCALS + 1],
+                    ..CompiledFunction::clone(function)
+                },
+                "constant 1 has more than 256 locals",
+            ),
+            (
+                CompiledFunction {
+                    locals: vec!["x".to_string(), "y".to_string()],
+                    ..CompiledFunction::clone(function)
+                },
+                "constant 1 doesn't have a fallback for each of its `let` locals",
+            ),
+            (
+                CompiledFunction {
+                    locals: vec!["x".to_string(), "y".to_string()],
+                    fallbacks: vec![Fallback::Global(1)],
+                    ..CompiledFunction::clone(function)
+                },
+                "constant 1 has a fallback out of range",
+            ),
+            (
+                CompiledFunction {
+                    locals: vec!["x".to_string(), "y".to_string()],
+                    fallbacks: vec![Fallback::Outer { depth: 1, index: 0 }],
+                    ..CompiledFunction::clone(function)
+                },
+                "constant 1 has a fallback out of range",
+            ),
+            (
+                returning([
+                    make(Opcode::GetOuter, &[1, 0]),
+                    make(Opcode::ReturnValue, &[]),
+                ]),
+                "outer local out of range at offset 0 of constant 1",
+            ),
+            (
+                returning([make(Opcode::Closure, &[1]), make(Opcode::ReturnValue, &[])]),
+                "function created in more than one place at offset 0 of constant 1",
+            ),
+        ];
+        for (function, expected) in cases {
+            let mut unverified = valid.clone();
+            unverified.bytecode.constants[1] = Object::CompiledFunction(Rc::new(function));
+            assert_eq!(
+                decode(&encode(&unverified)),
+                Err(DecodeError::Invalid(expected.to_string())),
+                "{}",
+                expected
+            );
+        }
+
+        let mut unverified = executable("1");
+        unverified.bytecode.main.locals.push("x".to_string());
+        assert_eq!(
+            decode(&encode(&unverified)),
+            Err(DecodeError::Invalid("the top level has locals".to_string()))
+        );
+    }
+}
@@ -1,32 +1,299 @@
+use crate::ast::{ParseError, Parser};
+use crate::diagnostics::{Diagnostic, Mode};
+use crate::eval::{self, Env, Environment, Object};
 use crate::lexer::Lexer;
+use crate::token::{SpannedToken, Token};
+use helper::MonkeyHelper;
+use rustyline::error::ReadlineError;
+use rustyline::history::FileHistory;
+use rustyline::Editor;
+use std::cell::RefCell;
 use std::io;
 use std::io::Write;
+use std::path::PathBuf;
+use std::rc::Rc;
 
-pub fn start() {
+mod helper;
+
+const HISTORY_FILE: &str = ".monkey_history";
+
+const HELP: &str = "Commands:
+    :eval        evaluate input and print its value (default)
+    :tokens      print the tokens of the input
+    :ast         print the parsed program
+    :env         list the current bindings
+    :reset       clear all bindings
+    :load FILE   evaluate FILE in the current environment
+    :help        print this message
+    :quit        leave the REPL (or press Ctrl-D)
+
+Use the arrow keys to edit and recall earlier input, Ctrl-R to search the history,
+Tab to complete keywords, builtins, bindings and commands, and Ctrl-C to discard the current input.";
+
+/// What the REPL does with input that isn't a command.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+enum Output {
+    Eval,
+    Tokens,
+    Ast,
+}
+
+/// Whether the REPL should keep reading input after a line was handled.
+#[derive(Debug, PartialEq, Eq)]
+enum Flow {
+    Continue,
+    Quit,
+}
+
+struct Repl {
+    env: Env,
+    output: Output,
+    mode: Mode,
+}
+
+impl Repl {
+    fn new() -> Self {
+        Self {
+            env: Rc::new(RefCell::new(Environment::new())),
+            output: Output::Eval,
+            mode: Mode::for_stderr(),
+        }
+    }
+
+    /// Handles one line of input, writing results to `out` and diagnostics to stderr.
+    fn handle(&mut self, line: &str, out: &mut impl Write) -> io::Result<Flow> {
+        let line = line.trim();
+        if line == "exit" {
+            return Ok(Flow::Quit);
+        }
+        match line.strip_prefix(':') {
+            Some(command) => self.command(command, out),
+            None => {
+                self.input(line, "<repl>", out)?;
+                Ok(Flow::Continue)
+            }
+        }
+    }
+
+    fn command(&mut self, command: &str, out: &mut impl Write) -> io::Result<Flow> {
+        let (name, argument) = match command.split_once(char::is_whitespace) {
+            Some((name, argument)) => (name, argument.trim()),
+            None => (command, ""),
+        };
+        match (name, argument) {
+            ("eval", "") => self.output = Output::Eval,
+            ("tokens", "") => self.output = Output::Tokens,
+            ("ast", "") => self.output = Output::Ast,
+            ("env", "") => {
+                for (name, value) in self.env.borrow().bindings() {
+                    writeln!(out, "{} = {}", name, value)?;
+                }
+            }
+            // The environment is cleared in place, as the completer shares it.
+            ("reset", "") => self.env.borrow_mut().clear(),
+            ("load", "") => writeln!(out, "usage: :load FILE")?,
+            ("load", path) => match std::fs::read_to_string(path) {
+                Ok(source) => self.evaluate(&source, path, out)?,
+                Err(error) => eprintln!("could not read {}: {}", path, error),
+            },
+            ("help", "") => writeln!(out, "{}", HELP)?,
+            ("quit" | "q", "") => return Ok(Flow::Quit),
+            _ => writeln!(out, "unknown command :{}, see :help", command)?,
+        }
+        Ok(Flow::Continue)
+    }
+
+    /// Processes `source` according to the current output mode.
+    fn input(&mut self, source: &str, origin: &str, out: &mut impl Write) -> io::Result<()> {
+        match self.output {
+            Output::Eval => self.evaluate(source, origin, out),
+            Output::Tokens => {
+                for SpannedToken { token, .. } in Lexer::new(source) {
+                    writeln!(out, "{:?}", token)?;
+                }
+                writeln!(out, "Eof")
+            }
+            Output::Ast => match Parser::new(source).parse() {
+                Ok(program) => write!(out, "{}", program),
+                Err(errors) => {
+                    self.report_parse_errors(&errors, source, origin);
+                    Ok(())
+                }
+            },
+        }
+    }
+
+    fn evaluate(&mut self, source: &str, origin: &str, out: &mut impl Write) -> io::Result<()> {
+        let program = match Parser::new(source).parse() {
+            Ok(program) => program,
+            Err(errors) => {
+                self.report_parse_errors(&errors, source, origin);
+                return Ok(());
+            }
+        };
+        match eval::eval(&program, &self.env) {
+            Ok(Object::Null) => {}
+            Ok(value) => writeln!(out, "{}", value)?,
+            Err(error) => eprint!(
+                "{}",
+                Diagnostic::from(&error).render(source, origin, self.mode)
+            ),
+        }
+        Ok(())
+    }
+
+    fn report_parse_errors(&self, errors: &[ParseError], source: &str, origin: &str) {
+        for error in errors {
+            eprint!(
+                "{}",
+                Diagnostic::from(error).render(source, origin, self.mode)
+            );
+        }
+    }
+}
+
+/// Returns whether `source` can be submitted, or whether the user is still typing it: either a
+/// bracket or string is left open, or the parser ran into the end of the input mid-statement.
+fn is_complete(source: &str) -> bool {
+    let mut depth: i64 = 0;
+    for SpannedToken { token, .. } in Lexer::new(source) {
+        match token {
+            Token::LParen | Token::LBrace | Token::LBracket => depth += 1,
+            Token::RParen | Token::RBrace | Token::RBracket => depth -= 1,
+            _ => {}
+        }
+    }
+    if depth > 0 {
+        return false;
+    }
+    match Parser::new(source).parse() {
+        Ok(_) => true,
+        Err(errors) => errors
+            .iter()
+            .all(|error| !matches!(error.found, Token::Eof | Token::UnterminatedString)),
+    }
+}
+
+/// The file in the user's home directory that keeps the REPL history between sessions.
+fn history_path() -> Option<PathBuf> {
+    let home = std::env::var_os("HOME").or_else(|| std::env::var_os("USERPROFILE"))?;
+    Some(PathBuf::from(home).join(HISTORY_FILE))
+}
+
+/// Runs the REPL on the terminal. Evaluating input may use `stack_limit` bytes of the native
+/// stack, as set with `Environment::set_stack_limit`.
+pub fn start(stack_limit: usize) {
+    let mut repl = Repl::new();
+    repl.env.borrow_mut().set_stack_limit(stack_limit);
+    let mut editor: Editor<MonkeyHelper, FileHistory> = match Editor::new() {
+        Ok(editor) => editor,
+        Err(error) => {
+            eprintln!("Failed to set up line editing: {}", error);
+            return;
+        }
+    };
+    editor.set_helper(Some(MonkeyHelper::new(Rc::clone(&repl.env))));
+    let history = history_path();
+    if let Some(path) = &history {
+        // A missing history file just means this is the first session.
+        let _ = editor.load_history(path);
+    }
+
+    let mut stdout = io::stdout();
+    let mut input = String::new();
     loop {
-        let mut input = String::new();
-        print!(">> ");
-        let _ = io::stdout().flush();
-        if io::stdin().read_line(&mut input).is_err() {
-            eprintln!("Failed to read input");
+        // Continuation lines are prompted differently; an empty one submits the input as is.
+        let prompt = if input.is_empty() { ">> " } else { ".. " };
+        let line = match editor.readline(prompt) {
+            Ok(line) => line,
+            Err(ReadlineError::Interrupted) => {
+                // Ctrl-C discards the current input, like in a shell.
+                input.clear();
+                continue;
+            }
+            Err(ReadlineError::Eof) => break,
+            Err(error) => {
+                eprintln!("Failed to read input: {}", error);
+                break;
+            }
+        };
+        let continuing = !input.is_empty();
+        input.push_str(&line);
+        input.push('\n');
+        let is_command = !continuing && input.trim_start().starts_with(':');
+        let force = continuing && line.trim().is_empty();
+        if !(is_command || force || is_complete(&input)) {
             continue;
         }
-        // Trim the input to handle extra newlines and spaces
-        let input = input.trim();
-
-        // Add an exit condition
-        if input == "exit" {
-            println!("Exiting...");
-            break;
+        let input = std::mem::take(&mut input);
+        if !input.trim().is_empty() {
+            let _ = editor.add_history_entry(input.trim_end());
         }
+        match repl.handle(&input, &mut stdout) {
+            Ok(Flow::Continue) => {}
+            Ok(Flow::Quit) => break,
+            Err(error) => eprintln!("Failed to write output: {}", error),
+        }
+    }
+    println!("Exiting...");
+    if let Some(path) = &history {
+        if let Err(error) = editor.save_history(path) {
+            eprintln!("Failed to save history to {}: {}", path.display(), error);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-        let mut lexer = Lexer::new(input);
-        while let Some(token) = lexer.next_token() {
-            println!("{:?}", token);
+    /// Feeds `lines` to a fresh REPL and returns everything written to the output.
+    fn run(lines: &[&str]) -> String {
+        let mut repl = Repl::new();
+        let mut out = Vec::new();
+        for line in lines {
+            if repl.handle(line, &mut out).unwrap() == Flow::Quit {
+                break;
+            }
         }
-        println!("Eof");
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn environment_persists_across_lines() {
+        assert_eq!(run(&["let a = 2;", "let b = a * 3;", "a + b"]), "8\n");
+        assert_eq!(run(&["let b = 1; let a = 2;", ":env"]), "a = 2\nb = 1\n");
+        assert_eq!(run(&["let a = 1;", ":reset", ":env", ":quit", "a"]), "");
+    }
+
+    #[test]
+    fn load_reports_unreadable_files_on_stderr() {
+        assert_eq!(run(&[":load /nonexistent/script.monkey", "1"]), "1\n");
+    }
+
+    #[test]
+    fn detects_incomplete_input() {
+        assert!(is_complete("let a = 1;"));
+        assert!(is_complete("let f = fn(x) {\n  x\n};"));
+        assert!(!is_complete("let f = fn(x) {"));
+        assert!(!is_complete("let f = fn(x) {\n  if (x) {\n"));
+        assert!(!is_complete("add(1,\n"));
+        assert!(!is_complete("let a = [1,\n"));
+        assert!(!is_complete("let a = "));
+        assert!(!is_complete("1 +"));
+        assert!(!is_complete("let s = \"first line\n"));
+        // Errors that more input can't fix are submitted right away.
+        assert!(is_complete("let = 1"));
+        assert!(is_complete(")"));
+    }
 
-        // let mut parser = Parser::new(input);
-        // println!("{:?}", parser.parse());
+    #[test]
+    fn output_modes() {
+        assert_eq!(
+            run(&[":tokens", "x + 1"]),
+            "Ident(\"x\")\nPlus\nInt(\"1\")\nEof\n"
+        );
+        assert_eq!(run(&[":ast", "let x = -1 * 2"]), "let x = ((-1) * 2);\n");
+        assert_eq!(run(&[":ast", ":eval", "1 + 2"]), "3\n");
     }
 }
@@ -0,0 +1,199 @@
+use crate::eval::Env;
+use crate::lexer::{Lexer, KEYWORDS};
+use crate::token::{SpannedToken, Token};
+use rustyline::completion::Completer;
+use rustyline::highlight::Highlighter;
+use rustyline::hint::Hinter;
+use rustyline::validate::Validator;
+use rustyline::{Context, Helper};
+use std::borrow::Cow;
+
+/// The REPL's meta-commands, offered when completing a word that starts with `:`.
+const COMMANDS: [&str; 8] = [
+    ":eval", ":tokens", ":ast", ":env", ":reset", ":load", ":help", ":quit",
+];
+
+/// Returns the ANSI style for a token, or `None` to leave it uncolored.
+fn style(token: &Token) -> Option<&'static str> {
+    match token {
+        Token::Function
+        | Token::Let
+        | Token::If
+        | Token::Else
+        | Token::True
+        | Token::False
+        | Token::Return => Some("1;35"),
+        Token::Ident(_) => Some("36"),
+        Token::Int(_) => Some("33"),
+        Token::Str(_) | Token::Interpolated(_) => Some("32"),
+        Token::Assign
+        | Token::Plus
+        | Token::Minus
+        | Token::Bang
+        | Token::Asterisk
+        | Token::Slash
+        | Token::LessThan
+        | Token::GreaterThan
+        | Token::Equal
+        | Token::NotEqual => Some("34"),
+        Token::Illegal(_) | Token::UnterminatedString | Token::InvalidEscape(_) => Some("1;37;41"),
+        Token::Comma
+        | Token::Semicolon
+        | Token::Colon
+        | Token::LParen
+        | Token::RParen
+        | Token::LBrace
+        | Token::RBrace
+        | Token::LBracket
+        | Token::RBracket
+        | Token::Eof => None,
+    }
+}
+
+/// Colors `line` token by token, leaving the whitespace between tokens untouched.
+fn highlight_line(line: &str) -> String {
+    let mut out = String::with_capacity(line.len());
+    let mut copied = 0;
+    for SpannedToken { token, span } in Lexer::new(line) {
+        // The lexer works on bytes, so a non-ASCII character turns into several illegal tokens;
+        // the first one paints the whole character.
+        if span.start < copied {
+            continue;
+        }
+        let mut end = span.end;
+        while !line.is_char_boundary(end) {
+            end += 1;
+        }
+        out.push_str(&line[copied..span.start]);
+        match style(&token) {
+            Some(style) => {
+                out.push_str(&format!("\x1b[{}m{}\x1b[0m", style, &line[span.start..end]))
+            }
+            None => out.push_str(&line[span.start..end]),
+        }
+        copied = end;
+    }
+    out.push_str(&line[copied..]);
+    out
+}
+
+/// Hooks the REPL into rustyline's line editor.
+pub(super) struct MonkeyHelper {
+    env: Env,
+}
+
+impl MonkeyHelper {
+    pub(super) fn new(env: Env) -> Self {
+        Self { env }
+    }
+
+    /// Returns where the word under the cursor starts and the names it could be completed to:
+    /// keywords, builtins and the names bound in the REPL's environment, or meta-commands after a
+    /// `:`.
+    fn candidates(&self, line: &str, pos: usize) -> (usize, Vec<String>) {
+        let start = line[..pos]
+            .rfind(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))
+            .map_or(0, |i| i + 1);
+        let word = &line[start..pos];
+
+        if line[..start].trim() == ":" {
+            let start = line.find(':').unwrap_or(0);
+            let word = &line[start..pos];
+            let commands = COMMANDS.iter().filter(|command| command.starts_with(word));
+            return (start, commands.map(|command| command.to_string()).collect());
+        }
+        if word.is_empty() {
+            return (pos, Vec::new());
+        }
+
+        let env = self.env.borrow();
+        let keywords = KEYWORDS.iter().map(|(keyword, _)| *keyword);
+        let builtins = env.builtins().names().into_iter();
+        let bindings = env.bindings().into_iter().map(|(name, _)| name);
+        let mut candidates: Vec<String> = keywords
+            .chain(builtins)
+            .chain(bindings)
+            .filter(|name| name.starts_with(word))
+            .map(String::from)
+            .collect();
+        candidates.sort();
+        candidates.dedup();
+        (start, candidates)
+    }
+}
+
+impl Completer for MonkeyHelper {
+    type Candidate = String;
+
+    fn complete(
+        &self,
+        line: &str,
+        pos: usize,
+        _ctx: &Context<'_>,
+    ) -> rustyline::Result<(usize, Vec<String>)> {
+        Ok(self.candidates(line, pos))
+    }
+}
+
+impl Hinter for MonkeyHelper {
+    type Hint = String;
+}
+
+impl Highlighter for MonkeyHelper {
+    fn highlight<'l>(&self, line: &'l str, _pos: usize) -> Cow<'l, str> {
+        Cow::Owned(highlight_line(line))
+    }
+
+    /// Every keystroke can change how the line lexes, so it is always highlighted again.
+    fn highlight_char(&self, _line: &str, _pos: usize, _forced: bool) -> bool {
+        true
+    }
+}
+
+impl Validator for MonkeyHelper {}
+
+impl Helper for MonkeyHelper {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::eval::{Environment, Object};
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    #[test]
+    fn completes_keywords_bindings_and_commands() {
+        let env = Rc::new(RefCell::new(Environment::new()));
+        env.borrow_mut().set("length", Object::Integer(1));
+        env.borrow_mut().set("left", Object::Integer(2));
+        let helper = MonkeyHelper::new(env);
+
+        assert_eq!(
+            helper.candidates("1 + le", 6),
+            (
+                4,
+                vec!["left".into(), "len".into(), "length".into(), "let".into()]
+            )
+        );
+        assert_eq!(helper.candidates("if (tr) {", 6), (4, vec!["true".into()]));
+        assert_eq!(helper.candidates("x + ", 4), (4, vec![]));
+        assert_eq!(helper.candidates(":re", 3), (0, vec![":reset".into()]));
+        assert_eq!(
+            helper.candidates(":", 1),
+            (0, COMMANDS.map(String::from).to_vec())
+        );
+    }
+
+    #[test]
+    fn highlights_tokens() {
+        assert_eq!(
+            highlight_line("let x = 5;"),
+            "\x1b[1;35mlet\x1b[0m \x1b[36mx\x1b[0m \x1b[34m=\x1b[0m \x1b[33m5\x1b[0m;"
+        );
+        assert_eq!(
+            highlight_line("f(a) @"),
+            "\x1b[36mf\x1b[0m(\x1b[36ma\x1b[0m) \x1b[1;37;41m@\x1b[0m"
+        );
+        assert_eq!(highlight_line("  é "), "  \x1b[1;37;41mé\x1b[0m ");
+    }
+}
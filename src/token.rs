@@ -1,11 +1,73 @@
-#[derive(PartialEq, Debug, Clone)]
-pub enum Token {
-    Illegal(String),
+use std::borrow::Cow;
+use std::fmt;
+
+/// A region of the source as a half-open range of byte offsets, together with the line and
+/// column where it starts. Lines and columns are 1-based; columns count bytes.
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Default)]
+pub struct Span {
+    pub start: usize,
+    pub end: usize,
+    pub line: usize,
+    pub column: usize,
+}
+
+impl Span {
+    /// Returns a span covering both `self` and everything up to the end of `other`.
+    pub fn to(self, other: Span) -> Span {
+        Span {
+            end: other.end,
+            ..self
+        }
+    }
+}
+
+/// A piece of an interpolated string literal.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum StringPart<'src> {
+    /// Text between interpolations, with its escape sequences already resolved.
+    Literal(Cow<'src, str>),
+    /// The source code between `${` and `}`, along with its location.
+    Code { source: Cow<'src, str>, span: Span },
+}
+
+impl StringPart<'_> {
+    pub fn into_owned(self) -> StringPart<'static> {
+        match self {
+            StringPart::Literal(text) => StringPart::Literal(Cow::Owned(text.into_owned())),
+            StringPart::Code { source, span } => StringPart::Code {
+                source: Cow::Owned(source.into_owned()),
+                span,
+            },
+        }
+    }
+}
+
+/// A `Token` together with its location in the source.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct SpannedToken<'src> {
+    pub token: Token<'src>,
+    pub span: Span,
+}
+
+/// A token of the language. Text is borrowed from the source where possible; only string literals
+/// with escape sequences own theirs. `into_owned` detaches a token from the source.
+#[derive(PartialEq, Eq, Debug, Clone)]
+pub enum Token<'src> {
+    Illegal(Cow<'src, str>),
     Eof,
 
+    /// A string literal that isn't closed before the end of the input.
+    UnterminatedString,
+    /// A string literal containing an unknown escape sequence, e.g. `\q`.
+    InvalidEscape(Cow<'src, str>),
+
     // Identifiers + literals
-    Ident(String),
-    Int(String),
+    Ident(Cow<'src, str>),
+    Int(Cow<'src, str>),
+    /// A string literal, with its escape sequences already resolved.
+    Str(Cow<'src, str>),
+    /// A string literal containing `${...}` interpolations.
+    Interpolated(Vec<StringPart<'src>>),
 
     // Operators
     Assign,
@@ -24,11 +86,14 @@ pub enum Token {
     // Delimiters
     Comma,
     Semicolon,
+    Colon,
 
     LParen,
     RParen,
     LBrace,
     RBrace,
+    LBracket,
+    RBracket,
 
     // Keywords
     Function,
@@ -40,15 +105,74 @@ pub enum Token {
     Return,
 }
 
-impl From<u8> for Token {
+/// A token that doesn't borrow from the source.
+pub type OwnedToken = Token<'static>;
+
+impl Token<'_> {
+    pub fn into_owned(self) -> OwnedToken {
+        let owned = |text: Cow<'_, str>| Cow::Owned(text.into_owned());
+        match self {
+            Token::Illegal(text) => Token::Illegal(owned(text)),
+            Token::InvalidEscape(text) => Token::InvalidEscape(owned(text)),
+            Token::Ident(text) => Token::Ident(owned(text)),
+            Token::Int(text) => Token::Int(owned(text)),
+            Token::Str(text) => Token::Str(owned(text)),
+            Token::Interpolated(parts) => {
+                Token::Interpolated(parts.into_iter().map(StringPart::into_owned).collect())
+            }
+            Token::Eof => Token::Eof,
+            Token::UnterminatedString => Token::UnterminatedString,
+            Token::Assign => Token::Assign,
+            Token::Plus => Token::Plus,
+            Token::Minus => Token::Minus,
+            Token::Bang => Token::Bang,
+            Token::Asterisk => Token::Asterisk,
+            Token::Slash => Token::Slash,
+            Token::LessThan => Token::LessThan,
+            Token::GreaterThan => Token::GreaterThan,
+            Token::Equal => Token::Equal,
+            Token::NotEqual => Token::NotEqual,
+            Token::Comma => Token::Comma,
+            Token::Semicolon => Token::Semicolon,
+            Token::Colon => Token::Colon,
+            Token::LParen => Token::LParen,
+            Token::RParen => Token::RParen,
+            Token::LBrace => Token::LBrace,
+            Token::RBrace => Token::RBrace,
+            Token::LBracket => Token::LBracket,
+            Token::RBracket => Token::RBracket,
+            Token::Function => Token::Function,
+            Token::Let => Token::Let,
+            Token::True => Token::True,
+            Token::False => Token::False,
+            Token::If => Token::If,
+            Token::Else => Token::Else,
+            Token::Return => Token::Return,
+        }
+    }
+}
+
+impl SpannedToken<'_> {
+    pub fn into_owned(self) -> SpannedToken<'static> {
+        SpannedToken {
+            token: self.token.into_owned(),
+            span: self.span,
+        }
+    }
+}
+
+impl From<u8> for OwnedToken {
     fn from(value: u8) -> Self {
         match value {
             b';' => Token::Semicolon,
+            b':' => Token::Colon,
             b'(' => Token::LParen,
             b')' => Token::RParen,
             b',' => Token::Comma,
             b'{' => Token::LBrace,
             b'}' => Token::RBrace,
+            b'[' => Token::LBracket,
+            b']' => Token::RBracket,
             b'+' => Token::Plus,
             b'-' => Token::Minus,
             b'*' => Token::Asterisk,
@@ -58,7 +182,76 @@ impl From<u8> for Token {
             b'=' => Token::Assign,
             b'!' => Token::Bang,
             0 => Token::Eof,
-            _ => Token::Illegal(String::from_utf8_lossy(&[value]).to_string()),
+            _ => Token::Illegal(Cow::Owned(String::from_utf8_lossy(&[value]).into_owned())),
+        }
+    }
+}
+
+impl fmt::Display for Token<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let text: &str = match self {
+            Token::Illegal(text) | Token::Ident(text) | Token::Int(text) => text,
+            Token::InvalidEscape(text) => text,
+            Token::Str(value) => return write!(f, "\"{}\"", escape(value)),
+            Token::Interpolated(parts) => {
+                write!(f, "\"")?;
+                for part in parts {
+                    match part {
+                        StringPart::Literal(text) => write!(f, "{}", escape(text))?,
+                        StringPart::Code { source, .. } => write!(f, "${{{}}}", source)?,
+                    }
+                }
+                return write!(f, "\"");
+            }
+            Token::UnterminatedString => "\"",
+            Token::Eof => "EOF",
+            Token::Assign => "=",
+            Token::Plus => "+",
+            Token::Minus => "-",
+            Token::Bang => "!",
+            Token::Asterisk => "*",
+            Token::Slash => "/",
+            Token::LessThan => "<",
+            Token::GreaterThan => ">",
+            Token::Equal => "==",
+            Token::NotEqual => "!=",
+            Token::Comma => ",",
+            Token::Semicolon => ";",
+            Token::Colon => ":",
+            Token::LParen => "(",
+            Token::RParen => ")",
+            Token::LBrace => "{",
+            Token::RBrace => "}",
+            Token::LBracket => "[",
+            Token::RBracket => "]",
+            Token::Function => "fn",
+            Token::Let => "let",
+            Token::True => "true",
+            Token::False => "false",
+            Token::If => "if",
+            Token::Else => "else",
+            Token::Return => "return",
+        };
+        write!(f, "{}", text)
+    }
+}
+
+/// Escapes `text` as the content of a string literal, without the quotes, that the lexer reads
+/// back as `text`. Control characters, which the lexer either can't read or that would be
+/// invisible, are written as `\u{...}`.
+pub fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    let mut chars = text.chars().peekable();
+    while let Some(ch) = chars.next() {
+        match ch {
+            '\n' => escaped.push_str("\\n"),
+            '\t' => escaped.push_str("\\t"),
+            '"' => escaped.push_str("\\\""),
+            '\\' => escaped.push_str("\\\\"),
+            '$' if chars.peek() == Some(&'{') => escaped.push_str("\\$"),
+            ch if ch.is_control() => escaped.push_str(&format!("\\u{{{:x}}}", ch as u32)),
+            ch => escaped.push(ch),
         }
     }
+    escaped
 }
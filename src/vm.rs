@@ -0,0 +1,517 @@
+use crate::ast::{InfixOperator, PrefixOperator};
+use crate::builtins::Builtins;
+use crate::code::{read_u16, CompiledFunction, Fallback, Opcode};
+use crate::compiler::Bytecode;
+use crate::eval::{
+    eval_index_expression, eval_infix_expression, eval_prefix_expression, is_truthy, EvalError,
+    EvalErrorKind, Hashable, Object, MAX_CALL_DEPTH,
+};
+use std::cell::RefCell;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::rc::Rc;
+
+/// A compiled function together with the locals of the call that created it.
+pub struct Closure {
+    pub function: Rc<CompiledFunction>,
+    /// `None` for functions created at the top level, whose names are all globals.
+    pub env: Option<Rc<Locals>>,
+}
+
+impl fmt::Debug for Closure {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Closure")
+            .field("function", &self.function.source)
+            .finish()
+    }
+}
+
+/// Closures are compared by identity, like the evaluator's functions.
+impl PartialEq for Closure {
+    fn eq(&self, other: &Self) -> bool {
+        std::ptr::eq(self, other)
+    }
+}
+
+impl Eq for Closure {}
+
+/// The local slots of a call. They are kept out of the stack so that the closures created during
+/// the call share them, and see what is bound to them afterwards, like the evaluator's
+/// environments.
+pub struct Locals {
+    function: Rc<CompiledFunction>,
+    /// Unset until the function's parameter or `let` statement for them binds them.
+    slots: RefCell<Vec<Option<Object>>>,
+    outer: Option<Rc<Locals>>,
+}
+
+impl Locals {
+    /// Returns the locals `depth` functions out from these.
+    fn enclosing(self: &Rc<Self>, depth: usize) -> &Rc<Self> {
+        let mut locals = self;
+        for _ in 0..depth {
+            locals = locals
+                .outer
+                .as_ref()
+                .expect("the compiler only refers to enclosing functions");
+        }
+        locals
+    }
+}
+
+/// The state of a call that is waiting for the function it called to return.
+struct Frame {
+    function: Rc<CompiledFunction>,
+    locals: Option<Rc<Locals>>,
+    ip: usize,
+    base: usize,
+}
+
+/// Runs `bytecode` and returns the value of the program, like `eval::eval` does for its AST.
+pub fn run(bytecode: &Bytecode, builtins: &Builtins) -> Result<Object, EvalError> {
+    let mut vm = Vm {
+        bytecode,
+        builtins,
+        globals: vec![None; bytecode.globals.len()],
+        stack: Vec::new(),
+        frames: Vec::new(),
+    };
+    vm.execute(Rc::new(bytecode.main.clone()))
+}
+
+struct Vm<'a> {
+    bytecode: &'a Bytecode,
+    builtins: &'a Builtins,
+    /// Unset until the program's `let` statement for them runs.
+    globals: Vec<Option<Object>>,
+    stack: Vec<Object>,
+    frames: Vec<Frame>,
+}
+
+impl Vm<'_> {
+    fn push(&mut self, object: Object) {
+        self.stack.push(object);
+    }
+
+    fn pop(&mut self) -> Object {
+        self.stack
+            .pop()
+            .expect("the compiler keeps the stack balanced")
+    }
+
+    /// Removes the top `count` values from the stack, in the order they were pushed.
+    fn pop_many(&mut self, count: usize) -> Vec<Object> {
+        self.stack.split_off(self.stack.len() - count)
+    }
+
+    /// Returns the value of the global at `index`, or the builtin of its name if it isn't set.
+    fn global(&self, index: usize) -> Result<Object, EvalErrorKind> {
+        match &self.globals[index] {
+            Some(value) => Ok(value.clone()),
+            None => self.builtin(&self.bytecode.globals[index]),
+        }
+    }
+
+    fn builtin(&self, name: &str) -> Result<Object, EvalErrorKind> {
+        self.builtins
+            .get(name)
+            .ok_or_else(|| EvalErrorKind::IdentifierNotFound(name.to_string()))
+    }
+
+    /// Returns the value of the slot at `index` in `locals`. Until the slot is bound, its name
+    /// refers to what it refers to outside of the function, as it does in the evaluator.
+    fn local(&self, locals: &Rc<Locals>, index: usize) -> Result<Object, EvalErrorKind> {
+        if let Some(value) = &locals.slots.borrow()[index] {
+            return Ok(value.clone());
+        }
+        // Parameters are bound by the call, so only the slots of `let` statements fall back.
+        match locals.function.fallbacks[index - locals.function.num_parameters] {
+            Fallback::Outer { depth, index } => self.local(locals.enclosing(depth), index),
+            Fallback::Global(index) => self.global(index),
+        }
+    }
+
+    fn execute(&mut self, main: Rc<CompiledFunction>) -> Result<Object, EvalError> {
+        let mut function = main;
+        let mut locals: Option<Rc<Locals>> = None;
+        let mut ip = 0;
+        let mut base = 0;
+        loop {
+            let instructions = &function.instructions;
+            let start = ip;
+            let opcode =
+                Opcode::try_from(instructions[start]).expect("the compiler emits valid opcodes");
+            ip += 1 + opcode.operand_widths().iter().sum::<usize>();
+            let operand = || read_u16(instructions, start + 1);
+            let byte = |offset: usize| instructions[start + 1 + offset] as usize;
+            let error = |kind| EvalError {
+                kind,
+                span: function.span_at(start).unwrap_or_default(),
+            };
+            let current = || locals.as_ref().expect("the top level has no locals");
+
+            match opcode {
+                Opcode::Constant => self.push(self.bytecode.constants[operand()].clone()),
+                Opcode::Pop => {
+                    self.pop();
+                }
+                Opcode::Add
+                | Opcode::Sub
+                | Opcode::Mul
+                | Opcode::Div
+                | Opcode::Equal
+                | Opcode::NotEqual
+                | Opcode::LessThan
+                | Opcode::GreaterThan => {
+                    let operator = match opcode {
+                        Opcode::Add => InfixOperator::Plus,
+                        Opcode::Sub => InfixOperator::Minus,
+                        Opcode::Mul => InfixOperator::Asterisk,
+                        Opcode::Div => InfixOperator::Slash,
+                        Opcode::Equal => InfixOperator::Equal,
+                        Opcode::NotEqual => InfixOperator::NotEqual,
+                        Opcode::LessThan => InfixOperator::LessThan,
+                        _ => InfixOperator::GreaterThan,
+                    };
+                    let right = self.pop();
+                    let left = self.pop();
+                    let result = eval_infix_expression(left, operator, right).map_err(error)?;
+                    self.push(result);
+                }
+                Opcode::Minus | Opcode::Bang => {
+                    let operator = match opcode {
+                        Opcode::Minus => PrefixOperator::Minus,
+                        _ => PrefixOperator::Bang,
+                    };
+                    let right = self.pop();
+                    let result = eval_prefix_expression(operator, right).map_err(error)?;
+                    self.push(result);
+                }
+                Opcode::True => self.push(Object::Boolean(true)),
+                Opcode::False => self.push(Object::Boolean(false)),
+                Opcode::Null => self.push(Object::Null),
+                Opcode::JumpNotTruthy => {
+                    if !is_truthy(&self.pop()) {
+                        ip = operand();
+                    }
+                }
+                Opcode::Jump => ip = operand(),
+                Opcode::GetGlobal => {
+                    let value = self.global(operand()).map_err(error)?;
+                    self.push(value);
+                }
+                Opcode::SetGlobal => {
+                    let value = self.pop();
+                    self.globals[operand()] = Some(value);
+                }
+                Opcode::GetLocal => {
+                    let value = self.local(current(), byte(0)).map_err(error)?;
+                    self.push(value);
+                }
+                Opcode::SetLocal => {
+                    let value = self.pop();
+                    current().slots.borrow_mut()[byte(0)] = Some(value);
+                }
+                Opcode::GetOuter => {
+                    let value = self
+                        .local(current().enclosing(byte(0)), byte(1))
+                        .map_err(error)?;
+                    self.push(value);
+                }
+                Opcode::Array => {
+                    let elements = self.pop_many(operand());
+                    self.push(Object::Array(elements));
+                }
+                Opcode::Hash => {
+                    let items = self.pop_many(2 * operand());
+                    let mut hash = BTreeMap::new();
+                    let mut items = items.into_iter();
+                    while let (Some(key), Some(value)) = (items.next(), items.next()) {
+                        hash.insert(key.hash_key().map_err(error)?, value);
+                    }
+                    self.push(Object::Hash(hash));
+                }
+                Opcode::HashKey => {
+                    let key = self.stack.last().expect("the key was just pushed");
+                    key.hash_key().map_err(error)?;
+                }
+                Opcode::Index => {
+                    let index = self.pop();
+                    let left = self.pop();
+                    let result = eval_index_expression(left, index).map_err(error)?;
+                    self.push(result);
+                }
+                Opcode::Interpolate => {
+                    let parts = self.pop_many(operand());
+                    let value: String = parts.iter().map(|part| part.to_string()).collect();
+                    self.push(Object::String(value));
+                }
+                Opcode::Call => {
+                    let count = byte(0);
+                    let callee = self.stack.len() - 1 - count;
+                    match self.stack[callee].clone() {
+                        Object::Closure(called) => {
+                            let expected = called.function.num_parameters;
+                            if count != expected {
+                                return Err(error(EvalErrorKind::WrongArgumentCount {
+                                    expected,
+                                    found: count,
+                                }));
+                            }
+                            if self.frames.len() == MAX_CALL_DEPTH {
+                                return Err(error(EvalErrorKind::StackOverflow));
+                            }
+                            let mut slots: Vec<Option<Object>> =
+                                self.stack.drain(callee + 1..).map(Some).collect();
+                            slots.resize(called.function.locals.len(), None);
+                            self.stack.truncate(callee);
+                            let called_locals = Rc::new(Locals {
+                                function: Rc::clone(&called.function),
+                                slots: RefCell::new(slots),
+                                outer: called.env.clone(),
+                            });
+                            self.frames.push(Frame {
+                                function: std::mem::replace(
+                                    &mut function,
+                                    Rc::clone(&called.function),
+                                ),
+                                locals: locals.replace(called_locals),
+                                ip,
+                                base,
+                            });
+                            ip = 0;
+                            base = callee;
+                        }
+                        Object::Builtin(builtin) => {
+                            let arguments = self.pop_many(count);
+                            self.pop();
+                            let result = builtin.call(arguments).map_err(error)?;
+                            self.push(result);
+                        }
+                        other => return Err(error(EvalErrorKind::NotAFunction(other.type_name()))),
+                    }
+                }
+                Opcode::ReturnValue => {
+                    let value = self.pop();
+                    let Some(frame) = self.frames.pop() else {
+                        return Ok(value);
+                    };
+                    // Drop what an early `return` left on the stack.
+                    self.stack.truncate(base);
+                    self.push(value);
+                    function = frame.function;
+                    locals = frame.locals;
+                    ip = frame.ip;
+                    base = frame.base;
+                }
+                Opcode::Closure => {
+                    let Object::CompiledFunction(constant) = &self.bytecode.constants[operand()]
+                    else {
+                        panic!("closures are only made from function constants");
+                    };
+                    self.push(Object::Closure(Rc::new(Closure {
+                        function: Rc::clone(constant),
+                        env: locals.clone(),
+                    })));
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ast::{Parser, MAX_NESTING};
+    use crate::compiler::compile;
+    use crate::eval::{self, Environment};
+    use std::cell::RefCell;
+
+    /// Runs `input` with both engines and checks that they agree, returning the printed result
+    /// or error message.
+    fn run_both(input: &str) -> String {
+        run_both_within(input, eval::DEFAULT_STACK_LIMIT)
+    }
+
+    /// Like `run_both`, letting the evaluator use `stack_limit` bytes of the native stack.
+    fn run_both_within(input: &str, stack_limit: usize) -> String {
+        let program = Parser::new(input).parse().unwrap();
+        let env = Rc::new(RefCell::new(Environment::new()));
+        env.borrow_mut().set_stack_limit(stack_limit);
+        let evaluated = eval::eval(&program, &env);
+        let executed = run(&compile(&program).unwrap(), &Builtins::standard());
+        match (evaluated, executed) {
+            (Ok(expected), Ok(actual)) => {
+                assert_eq!(actual.to_string(), expected.to_string(), "{}", input);
+                actual.to_string()
+            }
+            (Err(expected), Err(actual)) => {
+                assert_eq!(actual, expected, "{}", input);
+                actual.to_string()
+            }
+            (expected, actual) => panic!("{}: expected {:?}, got {:?}", input, expected, actual),
+        }
+    }
+
+    #[test]
+    fn agrees_with_the_evaluator() {
+        let cases = [
+            ("", "null"),
+            ("1; 2 * (3 + 4) - 10 / 2", "9"),
+            ("-(5) + 10 == 5 != false", "true"),
+            ("!(1 < 2) == (3 > 4)", "true"),
+            ("let a = 5; let b = a * 2; b - a", "5"),
+            ("5; let a = 1;", "null"),
+            ("if (1 > 2) { 10 }", "null"),
+            ("if (false) { 10 } else { let x = 2; }", "null"),
+            ("if (1) { 10; 20 } else { 30 }", "20"),
+            (r#""mon" + "key" == "monkey""#, "true"),
+            (r#"let n = 2; "${n} + ${n} = ${n + n}""#, "2 + 2 = 4"),
+            ("[1, 2 * 2, [3]][2][0]", "3"),
+            (
+                r#"{"a": 1, 2: true, false: [3]}"#,
+                r#"{2: true, false: [3], "a": 1}"#,
+            ),
+            (r#"{"a": 1}["b"]"#, "null"),
+            ("len([1, 2]) + len(\"abc\")", "5"),
+            ("push(rest([1, 2, 3]), 4)", "[2, 3, 4]"),
+            ("let len = fn(x) { 0 }; len([1])", "0"),
+            ("fn(a, b) { a + b }", "fn(a, b) { (a + b) }"),
+            ("let f = fn() { 1 }; f", "fn() { 1 }"),
+            ("fn() { }()", "null"),
+            ("fn() { return 1; 2 }()", "1"),
+            ("return 1; 2", "1"),
+            ("if (true) { if (true) { return 10; } return 1; }", "10"),
+            (
+                "let f = fn(x) { if (x > 1) { if (true) { return x; } } 0 }; f(5) + f(1)",
+                "5",
+            ),
+            (
+                "let one = fn() { let a = 1; a }; let two = fn() { let a = 2; a }; one() + two()",
+                "3",
+            ),
+            (
+                "let g = 50; let f = fn(x) { let g = 1; x + g }; f(1) + g",
+                "52",
+            ),
+            (
+                "let x = 1; fn() { let a = fn() { let b = fn() { x }; let r = b(); let x = 2; r }(); \
+                 let x = 3; a }()",
+                "1",
+            ),
+            (
+                "let newAdder = fn(a) { fn(b) { fn(c) { a + b + c } } }; newAdder(1)(2)(3)",
+                "6",
+            ),
+            (
+                "let fib = fn(n) { if (n < 2) { n } else { fib(n - 1) + fib(n - 2) } }; fib(15)",
+                "610",
+            ),
+            (
+                "let wrapper = fn() { let countDown = fn(x) { if (x == 0) { 0 } \
+                 else { countDown(x - 1) } }; countDown(3) }; wrapper()",
+                "0",
+            ),
+            ("let f = fn() { later }; let later = 7; f()", "7"),
+            (
+                "let map = fn(arr, f) { let iter = fn(arr, acc) { if (len(arr) == 0) { acc } \
+                 else { iter(rest(arr), push(acc, f(first(arr)))) } }; iter(arr, []) }; \
+                 map([1, 2, 3], fn(x) { x * x })",
+                "[1, 4, 9]",
+            ),
+            ("let x = 1; let f = fn(x) { x }; f(2) + x", "3"),
+            ("let f = fn(x, x) { x }; f(1, 2)", "2"),
+            (
+                "fn() { let a = fn() { b() }; let b = fn() { 1 }; a() }()",
+                "1",
+            ),
+            (
+                "let f = fn(){ let x = 1; let g = fn(){ x }; let x = 2; g() }; f()",
+                "2",
+            ),
+            ("fn(x) { if (x) { let y = 1; } y }(true)", "1"),
+            ("let x = 10; fn() { let a = x; let x = 2; a + x }()", "12"),
+            (
+                "fn() { let even = fn(n) { if (n == 0) { true } else { odd(n - 1) } }; \
+                 let odd = fn(n) { if (n == 0) { false } else { even(n - 1) } }; even(10) }()",
+                "true",
+            ),
+            ("fn(x, x) { fn() { x } }(1, 2)()", "2"),
+        ];
+        for (input, expected) in cases {
+            assert_eq!(run_both(input), expected, "{}", input);
+        }
+    }
+
+    #[test]
+    fn errors_match_the_evaluator() {
+        let cases = [
+            ("5 + true; 5", "type mismatch: INTEGER + BOOLEAN"),
+            ("-true", "unknown operator: -BOOLEAN"),
+            (
+                "if (10 > 1) { true + false; }",
+                "unknown operator: BOOLEAN + BOOLEAN",
+            ),
+            ("let x = 1 / 0;", "division by zero"),
+            ("foobar", "identifier not found: foobar"),
+            ("let x = x + 1", "identifier not found: x"),
+            (
+                "fn() { let f = fn() { y }; f() ; let y = 1; }()",
+                "identifier not found: y",
+            ),
+            (
+                "fn(x) { x }(1, 2)",
+                "wrong number of arguments: expected 1, found 2",
+            ),
+            ("len(1)", "argument to `len` not supported, got INTEGER"),
+            ("1(2)", "not a function: INTEGER"),
+            ("[1][true]", "index operator not supported: ARRAY[BOOLEAN]"),
+            ("{[1]: 2}", "unusable as hash key: ARRAY"),
+            ("{1: 2}[fn() { 1 }]", "unusable as hash key: FUNCTION"),
+            (
+                "let f = fn(x) {\n  x + true\n};\nf(1)",
+                "type mismatch: INTEGER + BOOLEAN",
+            ),
+            (
+                "let f = fn() { fn() { 1 } }; f() == f()",
+                "unknown operator: FUNCTION == FUNCTION",
+            ),
+        ];
+        for (input, expected) in cases {
+            assert_eq!(run_both(input), expected, "{}", input);
+        }
+    }
+
+    #[test]
+    fn deep_recursion_overflows() {
+        let program = Parser::new("let f = fn(x) { f(x + 1) }; f(0)")
+            .parse()
+            .unwrap();
+        let error = run(&compile(&program).unwrap(), &Builtins::standard()).unwrap_err();
+        assert_eq!(error.kind, EvalErrorKind::StackOverflow);
+        assert_eq!((error.span.start, error.span.end), (16, 24));
+    }
+
+    #[test]
+    fn runs_the_deepest_nesting_the_parser_allows() {
+        let input = format!("fn() {{ {}1 }}()", "-".repeat(MAX_NESTING - 3));
+        assert_eq!(run_both(&input), "-1");
+    }
+
+    #[test]
+    fn engines_share_the_call_depth_limit() {
+        // The evaluator needs a big stack to reach the limit in debug builds.
+        let thread = std::thread::Builder::new().stack_size(256 << 20);
+        let handle = thread.spawn(|| {
+            let f = "let f = fn(n) { if (n == 0) { 0 } else { 1 + f(n - 1) } };";
+            let deepest = MAX_CALL_DEPTH - 1;
+            let input = format!("{} f({})", f, deepest);
+            assert_eq!(run_both_within(&input, 128 << 20), deepest.to_string());
+            for n in [MAX_CALL_DEPTH, 5000] {
+                let input = format!("{} f({})", f, n);
+                assert_eq!(run_both_within(&input, 128 << 20), "stack overflow");
+            }
+        });
+        handle.unwrap().join().unwrap();
+    }
+}